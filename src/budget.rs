@@ -0,0 +1,91 @@
+//! The budget of work a preemptible operation may do before it is preempted.
+//!
+//! `finalise` and `revoke` charge one unit after each slot they delete or reduce. When the budget
+//! is exhausted, the operation returns `EXCEPTION_PREEMTED` and can be restarted, keeping all the
+//! work done so far. A unit of work is always done before it is charged, so an operation makes
+//! progress with any budget.
+
+#[cfg(feature = "extern_deps")]
+use crate::deps::preemptionPoint;
+#[cfg(feature = "extern_deps")]
+use sel4_common::structures::exception_t;
+
+/// The budget is exhausted, the operation must be preempted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preempted;
+
+/// A source of work units for preemptible operations.
+pub trait WorkBudget {
+    /// Charge `units` of work which has just been done.
+    fn charge(&mut self, units: usize) -> Result<(), Preempted>;
+}
+
+/// Never preempts, e.g. at boot time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unbounded;
+
+impl WorkBudget for Unbounded {
+    #[inline]
+    fn charge(&mut self, _units: usize) -> Result<(), Preempted> {
+        Ok(())
+    }
+}
+
+/// Preempts once a fixed number of units has been charged, deterministic for latency-critical
+/// callers and tests.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedBudget {
+    remaining: usize,
+}
+
+impl FixedBudget {
+    pub fn new(units: usize) -> Self {
+        FixedBudget { remaining: units }
+    }
+
+    /// The units left before the next preemption.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Give the budget `units` more, e.g. before restarting a preempted operation.
+    #[inline]
+    pub fn refill(&mut self, units: usize) {
+        self.remaining = units;
+    }
+}
+
+impl WorkBudget for FixedBudget {
+    fn charge(&mut self, units: usize) -> Result<(), Preempted> {
+        if units >= self.remaining {
+            self.remaining = 0;
+            Err(Preempted)
+        } else {
+            self.remaining -= units;
+            Ok(())
+        }
+    }
+}
+
+/// The budget of the kernel: `preemptionPoint` counts the units in `ksWorkUnitsCompleted` and
+/// preempts when the limit is reached and an interrupt is pending.
+///
+/// `preemptionPoint` adds a single unit per call, so a charge of `units` calls it up to `units`
+/// times. Every operation of this crate charges one unit at a time, which is one call per charge.
+/// The calls stop at the first preemption, the kernel has reset the count by then.
+#[cfg(feature = "extern_deps")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KernelPreemptionPoint;
+
+#[cfg(feature = "extern_deps")]
+impl WorkBudget for KernelPreemptionPoint {
+    fn charge(&mut self, units: usize) -> Result<(), Preempted> {
+        for _ in 0..units {
+            if unsafe { preemptionPoint() } != exception_t::EXCEPTION_NONE {
+                return Err(Preempted);
+            }
+        }
+        Ok(())
+    }
+}
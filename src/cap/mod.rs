@@ -1,9 +1,13 @@
 //! 该模块定义了几乎全部的`capability`，可以在`sel4_common`中找到`plus_define_bitfield!`宏的具体实现，
 //! 该宏在生成`capability`的同时，会生成每个字段的`get``set`方法
 
+pub mod region;
+pub mod view;
 pub mod zombie;
 
-use sel4_common::{plus_define_bitfield, sel4_config::*, utils::pageBitsForSize, MASK};
+use crate::cap_rights::{seL4_CapRights_t, vm_rights_t};
+use view::{EndpointCap, FrameCap, IrqHandlerCap, NotificationCap};
+use sel4_common::{plus_define_bitfield, sel4_config::*, MASK};
 
 
 #[repr(C)]
@@ -30,7 +34,7 @@ impl CNodeCapData {
 }
 
 /// All types of caps;
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum CapTag {
     CapNullCap = 0,
     CapUntypedCap = 2,
@@ -49,6 +53,32 @@ pub enum CapTag {
     CapASIDPoolCap = 13,
 }
 
+impl TryFrom<u8> for CapTag {
+    /// The raw value is given back when it is not a valid cap type.
+    type Error = u8;
+
+    fn try_from(tag: u8) -> Result<Self, Self::Error> {
+        match tag {
+            0 => Ok(CapTag::CapNullCap),
+            2 => Ok(CapTag::CapUntypedCap),
+            4 => Ok(CapTag::CapEndpointCap),
+            6 => Ok(CapTag::CapNotificationCap),
+            8 => Ok(CapTag::CapReplyCap),
+            10 => Ok(CapTag::CapCNodeCap),
+            12 => Ok(CapTag::CapThreadCap),
+            14 => Ok(CapTag::CapIrqControlCap),
+            16 => Ok(CapTag::CapIrqHandlerCap),
+            18 => Ok(CapTag::CapZombieCap),
+            20 => Ok(CapTag::CapDomainCap),
+            1 => Ok(CapTag::CapFrameCap),
+            3 => Ok(CapTag::CapPageTableCap),
+            11 => Ok(CapTag::CapASIDControlCap),
+            13 => Ok(CapTag::CapASIDPoolCap),
+            _ => Err(tag),
+        }
+    }
+}
+
 /// cap_t 表示一个capability，由两个机器字组成，包含了类型、对象元数据以及指向内核对象的指针。
 /// 每个类型的capability的每个字段都实现了get和set方法。
 /// 
@@ -136,7 +166,7 @@ impl cap_t {
         if self.isArchCap() {
             return self.clone();
         }
-        match self.get_cap_type() {
+        match self.cap_type_or_null() {
             CapTag::CapEndpointCap => {
                 if !preserve && self.get_ep_badge() == 0 {
                     let mut new_cap = self.clone();
@@ -173,18 +203,38 @@ impl cap_t {
         }
     }
 
+    /// Decode the type of the cap, the raw type is returned as error if it is not a valid `CapTag`,
+    /// which happens when the cap is read from corrupted or uninitialised memory.
+    #[inline]
+    pub fn try_cap_type(&self) -> Result<CapTag, usize> {
+        CapTag::try_from(self.get_type() as u8).map_err(|tag| tag as usize)
+    }
+
+    /// Get the type of the cap, a cap whose type field is not a valid `CapTag` reads as a null cap.
+    ///
+    /// The caps in slots may come from corrupted memory, so the cspace operations decode them with
+    /// this: a cap of unknown type has no object, is never derived and deleting it does nothing.
+    #[inline]
+    pub fn cap_type_or_null(&self) -> CapTag {
+        self.try_cap_type().unwrap_or(CapTag::CapNullCap)
+    }
+
+    /// Get the type of the cap, the same as `cap_type_or_null`: a cap whose type field is not a
+    /// valid `CapTag` reads as a null cap. Use `try_cap_type` to tell such a cap from a null cap.
+    #[inline]
     pub fn get_cap_type(&self) -> CapTag {
-        unsafe { core::mem::transmute::<u8, CapTag>(self.get_type() as u8) }
+        self.cap_type_or_null()
     }
 
     pub fn get_cap_ptr(&self) -> usize {
-        match self.get_cap_type() {
+        match self.cap_type_or_null() {
             CapTag::CapUntypedCap => self.get_untyped_ptr(),
             CapTag::CapEndpointCap => self.get_ep_ptr(),
             CapTag::CapNotificationCap => self.get_nf_ptr(),
+            CapTag::CapReplyCap => self.get_reply_tcb_ptr(),
             CapTag::CapCNodeCap => self.get_cnode_ptr(),
             CapTag::CapThreadCap => self.get_tcb_ptr(),
-            CapTag::CapZombieCap => self.get_zombie_ptr(),
+            CapTag::CapZombieCap => self.get_zombie_ptr().unwrap_or(0),
             CapTag::CapFrameCap => self.get_frame_base_ptr(),
             CapTag::CapPageTableCap => self.get_pt_base_ptr(),
             CapTag::CapASIDPoolCap => self.get_asid_pool(),
@@ -192,22 +242,15 @@ impl cap_t {
         }
     }
 
-    /// 获得每一个`cap`管理的对象的大小
+    /// 获得每一个`cap`管理的对象的大小，没有对象的`cap`返回0
+    #[inline]
     pub fn get_cap_size_bits(&self) -> usize {
-        match self.get_cap_type() {
-            CapTag::CapUntypedCap => self.get_untyped_block_size(),
-            CapTag::CapEndpointCap => seL4_EndpointBits,
-            CapTag::CapNotificationCap => seL4_NotificationBits,
-            CapTag::CapCNodeCap => self.get_cnode_radix() + seL4_SlotBits,
-            CapTag::CapPageTableCap => PT_SIZE_BITS,
-            CapTag::CapReplyCap => seL4_ReplyBits,
-            _ => 0,
-        }
+        self.object_size_bits().unwrap_or(0)
     }
     /// 判断是否该`cap`是否与内存地址绑定，对应的对象是否占用内存空间，`get_cap_ptr`中下列`cap`均有指针指向内存地址，
     /// 所以下面这些指针都是`physical`的
     pub fn get_cap_is_physical(&self) -> bool {
-        match self.get_cap_type() {
+        match self.cap_type_or_null() {
             CapTag::CapUntypedCap
             | CapTag::CapEndpointCap
             | CapTag::CapNotificationCap
@@ -220,74 +263,85 @@ impl cap_t {
             _ => false,
         }
     }
+    /// Reduce the rights of the cap to those allowed by `rights`, caps without rights are returned unchanged.
+    pub fn mask_rights(&self, rights: seL4_CapRights_t) -> Self {
+        if self.isArchCap() {
+            return self.arch_mask_rights(&rights);
+        }
+        let mut new_cap = self.clone();
+        match self.cap_type_or_null() {
+            CapTag::CapEndpointCap => {
+                new_cap.set_ep_can_send(self.get_ep_can_send() & rights.get_allow_write());
+                new_cap.set_ep_can_receive(self.get_ep_can_receive() & rights.get_allow_read());
+                new_cap.set_ep_can_grant(self.get_ep_can_grant() & rights.get_allow_grant());
+                new_cap.set_ep_can_grant_reply(
+                    self.get_ep_can_grant_reply() & rights.get_allow_grant_reply(),
+                );
+            }
+            CapTag::CapNotificationCap => {
+                new_cap.set_nf_can_send(self.get_nf_can_send() & rights.get_allow_write());
+                new_cap.set_nf_can_receive(self.get_nf_can_receive() & rights.get_allow_read());
+            }
+            CapTag::CapReplyCap => {
+                new_cap.set_reply_can_grant(self.get_reply_can_grant() & rights.get_allow_grant());
+            }
+            _ => {}
+        }
+        new_cap
+    }
+
+    fn arch_mask_rights(&self, rights: &seL4_CapRights_t) -> Self {
+        let mut new_cap = self.clone();
+        if self.cap_type_or_null() == CapTag::CapFrameCap {
+            let vm_rights = vm_rights_t::from_word(self.get_frame_vm_rights()).mask(rights);
+            new_cap.set_frame_vm_rights(vm_rights.to_word());
+        }
+        new_cap
+    }
+
     /// 判断该`Cap`是否与架构相关，如`CapPageTableCap`因为不同架构页表不同，该`cap`明显与架构有关
     pub fn isArchCap(&self) -> bool {
-        self.get_cap_type() as usize % 2 != 0
+        self.cap_type_or_null() as usize % 2 != 0
     }
 }
 
 
 /// 判断两个cap指向的内核对象是否是同一个内存区域
+///
+/// An untyped covers the physical caps whose object lies inside it, and a frame the frames inside
+/// it. The other caps with an object cover the caps of the same type referring to the same
+/// `object_region`, zombies cover nothing.
 pub fn same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
-    match cap1.get_cap_type() {
-        CapTag::CapUntypedCap => {
-            if cap2.get_cap_is_physical() {
-                let aBase = cap1.get_untyped_ptr();
-                let bBase = cap2.get_cap_ptr();
-
-                let aTop = aBase + MASK!(cap1.get_untyped_block_size());
-                let bTop = bBase + MASK!(cap2.get_cap_size_bits());
-                return (aBase <= bBase) && (bTop <= aTop) && (bBase <= bTop);
-            }
-
-            return false;
-        }
-        CapTag::CapFrameCap => {
-            if cap2.get_cap_type() == CapTag::CapFrameCap {
-                let botA = cap1.get_frame_base_ptr();
-                let botB = cap2.get_frame_base_ptr();
-                let topA = botA + MASK!(pageBitsForSize(cap1.get_frame_size()));
-                let topB = botB + MASK!(pageBitsForSize(cap2.get_frame_size()));
-                return (botA <= botB) && (topA >= topB) && (botB <= topB);
-            }
-            false
-        }
+    let tag1 = cap1.cap_type_or_null();
+    let tag2 = cap2.cap_type_or_null();
+    match tag1 {
+        CapTag::CapUntypedCap => cap2.get_cap_is_physical() && contains_object(cap1, cap2),
+        CapTag::CapFrameCap => tag2 == CapTag::CapFrameCap && contains_object(cap1, cap2),
         CapTag::CapEndpointCap
         | CapTag::CapNotificationCap
+        | CapTag::CapReplyCap
+        | CapTag::CapCNodeCap
+        | CapTag::CapThreadCap
         | CapTag::CapPageTableCap
-        | CapTag::CapASIDPoolCap
-        | CapTag::CapThreadCap => {
-            if cap2.get_cap_type() == cap1.get_cap_type() {
-                return cap1.get_cap_ptr() == cap2.get_cap_ptr();
-            }
-            false
-        }
-        CapTag::CapASIDControlCap | CapTag::CapDomainCap => {
-            if cap2.get_cap_type() == cap1.get_cap_type() {
-                return true;
-            }
-            false
-        }
-        CapTag::CapCNodeCap => {
-            if cap2.get_cap_type() == CapTag::CapCNodeCap {
-                return (cap1.get_cnode_ptr() == cap2.get_cnode_ptr())
-                    && (cap1.get_cnode_radix() == cap2.get_cnode_radix());
-            }
-            false
+        | CapTag::CapASIDPoolCap => tag2 == tag1 && cap1.object_region() == cap2.object_region(),
+        CapTag::CapASIDControlCap | CapTag::CapDomainCap => tag2 == tag1,
+        CapTag::CapIrqControlCap => {
+            tag2 == CapTag::CapIrqControlCap || tag2 == CapTag::CapIrqHandlerCap
         }
-        CapTag::CapIrqControlCap => match cap2.get_cap_type() {
-            CapTag::CapIrqControlCap | CapTag::CapIrqHandlerCap => true,
-            _ => false,
+        CapTag::CapIrqHandlerCap => match IrqHandlerCap::try_from(*cap2) {
+            Ok(irq) => cap1.get_irq_handler() == irq.irq(),
+            Err(_) => false,
         },
-        CapTag::CapIrqHandlerCap => {
-            if cap2.get_cap_type() == CapTag::CapIrqHandlerCap {
-                return cap1.get_irq_handler() == cap2.get_irq_handler();
-            }
-            false
-        }
-        _ => {
-            return false;
-        }
+        CapTag::CapNullCap | CapTag::CapZombieCap => false,
+    }
+}
+
+/// `cap2`的对象是否在`cap1`的对象之内
+#[inline]
+fn contains_object(cap1: &cap_t, cap2: &cap_t) -> bool {
+    match (cap1.object_region(), cap2.object_region()) {
+        (Some(a), Some(b)) => a.contains(&b),
+        _ => false,
     }
 }
 
@@ -297,11 +351,11 @@ pub fn same_region_as(cap1: &cap_t, cap2: &cap_t) -> bool {
 /// A special case is that cap2 is a untyped_cap derived from cap1, in this case, cap1 will excute
 /// setUntypedCapAsFull, so you can assume cap1 and cap2 are different.
 pub fn same_object_as(cap1: &cap_t, cap2: &cap_t) -> bool {
-    if cap1.get_cap_type() == CapTag::CapUntypedCap {
+    if cap1.cap_type_or_null() == CapTag::CapUntypedCap {
         return false;
     }
-    if cap1.get_cap_type() == CapTag::CapIrqControlCap
-        && cap2.get_cap_type() == CapTag::CapIrqHandlerCap
+    if cap1.cap_type_or_null() == CapTag::CapIrqControlCap
+        && cap2.cap_type_or_null() == CapTag::CapIrqHandlerCap
     {
         return false;
     }
@@ -312,10 +366,10 @@ pub fn same_object_as(cap1: &cap_t, cap2: &cap_t) -> bool {
 }
 
 fn arch_same_object_as(cap1: &cap_t, cap2: &cap_t) -> bool {
-    if cap1.get_cap_type() == CapTag::CapFrameCap && cap2.get_cap_type() == CapTag::CapFrameCap {
-        return cap1.get_frame_base_ptr() == cap2.get_frame_base_ptr()
-            && cap1.get_frame_size() == cap2.get_frame_size()
-            && (cap1.get_frame_is_device() == 0) == (cap2.get_frame_is_device() == 0);
+    if let (Ok(frame1), Ok(frame2)) = (FrameCap::try_from(*cap1), FrameCap::try_from(*cap2)) {
+        return frame1.base_ptr() == frame2.base_ptr()
+            && frame1.size() == frame2.size()
+            && frame1.is_device() == frame2.is_device();
     }
     same_region_as(cap1, cap2)
 }
@@ -326,19 +380,20 @@ pub fn is_cap_revocable(derived_cap: &cap_t, src_cap: &cap_t) -> bool {
         return false;
     }
 
-    match derived_cap.get_cap_type() {
+    match derived_cap.cap_type_or_null() {
         CapTag::CapEndpointCap => {
-            assert_eq!(src_cap.get_cap_type(), CapTag::CapEndpointCap);
-            return derived_cap.get_ep_badge() != src_cap.get_ep_badge();
+            let src = EndpointCap::try_from(*src_cap).expect("endpoint cap derived from other cap");
+            return derived_cap.get_ep_badge() != src.badge();
         }
 
         CapTag::CapNotificationCap => {
-            assert_eq!(src_cap.get_cap_type(), CapTag::CapNotificationCap);
-            return derived_cap.get_nf_badge() != src_cap.get_nf_badge();
+            let src = NotificationCap::try_from(*src_cap)
+                .expect("notification cap derived from other cap");
+            return derived_cap.get_nf_badge() != src.badge();
         }
 
         CapTag::CapIrqHandlerCap => {
-            return src_cap.get_cap_type() == CapTag::CapIrqControlCap;
+            return src_cap.cap_type_or_null() == CapTag::CapIrqControlCap;
         }
 
         CapTag::CapUntypedCap => {
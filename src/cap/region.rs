@@ -0,0 +1,88 @@
+//! The memory occupied by the object a cap refers to.
+//!
+//! Every `CapTag` is covered: caps whose object lives in kernel memory have an `ObjectRegion`,
+//! the others (null, IRQ, domain and ASID control caps) have none. `same_region_as` and the
+//! untyped checks of `mdb` are built on it.
+
+use super::{cap_t, zombie::ZombieKind, CapTag};
+use sel4_common::sel4_config::{
+    asidLowBits, seL4_EndpointBits, seL4_NotificationBits, seL4_SlotBits, seL4_TCBBits,
+    PT_SIZE_BITS,
+};
+use sel4_common::{utils::pageBitsForSize, MASK};
+
+/// An ASID pool holds a pointer for each of the `BIT!(asidLowBits)` ASIDs.
+pub const seL4_ASIDPoolBits: usize = asidLowBits + 3;
+
+/// A naturally aligned region of `BIT!(size_bits)` bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObjectRegion {
+    pub base: usize,
+    pub size_bits: usize,
+}
+
+impl ObjectRegion {
+    /// The last byte of the region.
+    #[inline]
+    pub fn top(&self) -> usize {
+        self.base + MASK!(self.size_bits)
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &ObjectRegion) -> bool {
+        self.base <= other.base && other.top() <= self.top()
+    }
+
+    #[inline]
+    pub fn overlaps(&self, other: &ObjectRegion) -> bool {
+        self.base <= other.top() && other.base <= self.top()
+    }
+}
+
+impl cap_t {
+    /// The memory of the object the cap refers to, `None` if there is no such object.
+    ///
+    /// TCB, TCB zombie and reply caps all refer to the whole TCB, including its CNode slots. Caps of
+    /// unknown type and zombies of invalid type have no object.
+    pub fn object_region(&self) -> Option<ObjectRegion> {
+        let (base, size_bits) = match self.cap_type_or_null() {
+            CapTag::CapUntypedCap => (self.get_untyped_ptr(), self.get_untyped_block_size()),
+            CapTag::CapEndpointCap => (self.get_ep_ptr(), seL4_EndpointBits),
+            CapTag::CapNotificationCap => (self.get_nf_ptr(), seL4_NotificationBits),
+            CapTag::CapReplyCap => (tcb_base(self.get_reply_tcb_ptr()), seL4_TCBBits),
+            CapTag::CapCNodeCap => (self.get_cnode_ptr(), self.get_cnode_radix() + seL4_SlotBits),
+            CapTag::CapThreadCap => (tcb_base(self.get_tcb_ptr()), seL4_TCBBits),
+            CapTag::CapZombieCap => {
+                let size_bits = match ZombieKind::from_word(self.get_zombie_type())? {
+                    ZombieKind::Tcb => seL4_TCBBits,
+                    ZombieKind::CNode { radix } => radix + seL4_SlotBits,
+                };
+                (self.get_zombie_ptr()?, size_bits)
+            }
+            CapTag::CapFrameCap => (
+                self.get_frame_base_ptr(),
+                pageBitsForSize(self.get_frame_size()),
+            ),
+            CapTag::CapPageTableCap => (self.get_pt_base_ptr(), PT_SIZE_BITS),
+            CapTag::CapASIDPoolCap => (self.get_asid_pool(), seL4_ASIDPoolBits),
+            CapTag::CapNullCap
+            | CapTag::CapIrqControlCap
+            | CapTag::CapIrqHandlerCap
+            | CapTag::CapDomainCap
+            | CapTag::CapASIDControlCap => return None,
+        };
+        Some(ObjectRegion { base, size_bits })
+    }
+
+    /// The size of the object the cap refers to, `None` if there is no such object.
+    #[inline]
+    pub fn object_size_bits(&self) -> Option<usize> {
+        self.object_region().map(|region| region.size_bits)
+    }
+}
+
+/// `TCB`的指针指向`TCB_OFFSET`处，对象从其所在的`BIT!(seL4_TCBBits)`对齐处开始
+#[inline]
+fn tcb_base(tcb_ptr: usize) -> usize {
+    tcb_ptr & !MASK!(seL4_TCBBits)
+}
@@ -0,0 +1,320 @@
+//! Typed views of `cap_t`, each view can only be obtained from a cap with the matching `CapTag`
+//! and only exposes the fields valid for that type.
+//!
+//! ```ignore
+//! if let Ok(ep) = EndpointCap::try_from(cap) {
+//!     let badge = ep.badge();
+//! }
+//! ```
+
+use super::{cap_t, zombie::ZombieKind, CapTag};
+use crate::cap_rights::vm_rights_t;
+
+macro_rules! define_cap_view {
+    ($(#[$attr:meta])* $name:ident, $tag:path $(, $valid:path)?) => {
+        $(#[$attr])*
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct $name(cap_t);
+
+        impl TryFrom<cap_t> for $name {
+            /// The cap is given back when its type does not match or its fields are not valid.
+            type Error = cap_t;
+
+            #[inline]
+            fn try_from(cap: cap_t) -> Result<Self, Self::Error> {
+                if cap.try_cap_type() == Ok($tag) $(&& $valid(&cap))? {
+                    Ok($name(cap))
+                } else {
+                    Err(cap)
+                }
+            }
+        }
+
+        impl From<$name> for cap_t {
+            #[inline]
+            fn from(view: $name) -> Self {
+                view.0
+            }
+        }
+
+        impl $name {
+            #[inline]
+            pub fn as_cap(&self) -> &cap_t {
+                &self.0
+            }
+        }
+    };
+}
+
+define_cap_view!(
+    /// View of `CapUntypedCap`.
+    UntypedCap,
+    CapTag::CapUntypedCap
+);
+define_cap_view!(
+    /// View of `CapEndpointCap`.
+    EndpointCap,
+    CapTag::CapEndpointCap
+);
+define_cap_view!(
+    /// View of `CapNotificationCap`.
+    NotificationCap,
+    CapTag::CapNotificationCap
+);
+define_cap_view!(
+    /// View of `CapReplyCap`.
+    ReplyCap,
+    CapTag::CapReplyCap
+);
+define_cap_view!(
+    /// View of `CapCNodeCap`.
+    CNodeCap,
+    CapTag::CapCNodeCap
+);
+define_cap_view!(
+    /// View of `CapThreadCap`.
+    ThreadCap,
+    CapTag::CapThreadCap
+);
+define_cap_view!(
+    /// View of `CapIrqHandlerCap`.
+    IrqHandlerCap,
+    CapTag::CapIrqHandlerCap
+);
+define_cap_view!(
+    /// View of `CapZombieCap`, only for a zombie with a valid type and number.
+    ZombieCap,
+    CapTag::CapZombieCap,
+    cap_t::is_valid_zombie
+);
+define_cap_view!(
+    /// View of `CapFrameCap`.
+    FrameCap,
+    CapTag::CapFrameCap
+);
+define_cap_view!(
+    /// View of `CapPageTableCap`.
+    PageTableCap,
+    CapTag::CapPageTableCap
+);
+define_cap_view!(
+    /// View of `CapASIDPoolCap`.
+    ASIDPoolCap,
+    CapTag::CapASIDPoolCap
+);
+
+impl UntypedCap {
+    #[inline]
+    pub fn ptr(&self) -> usize {
+        self.0.get_untyped_ptr()
+    }
+
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        self.0.get_untyped_block_size()
+    }
+
+    #[inline]
+    pub fn free_index(&self) -> usize {
+        self.0.get_untyped_free_index()
+    }
+
+    #[inline]
+    pub fn is_device(&self) -> bool {
+        self.0.get_untyped_is_device() != 0
+    }
+}
+
+impl EndpointCap {
+    #[inline]
+    pub fn ptr(&self) -> usize {
+        self.0.get_ep_ptr()
+    }
+
+    #[inline]
+    pub fn badge(&self) -> usize {
+        self.0.get_ep_badge()
+    }
+
+    #[inline]
+    pub fn can_send(&self) -> bool {
+        self.0.get_ep_can_send() != 0
+    }
+
+    #[inline]
+    pub fn can_receive(&self) -> bool {
+        self.0.get_ep_can_receive() != 0
+    }
+
+    #[inline]
+    pub fn can_grant(&self) -> bool {
+        self.0.get_ep_can_grant() != 0
+    }
+
+    #[inline]
+    pub fn can_grant_reply(&self) -> bool {
+        self.0.get_ep_can_grant_reply() != 0
+    }
+}
+
+impl NotificationCap {
+    #[inline]
+    pub fn ptr(&self) -> usize {
+        self.0.get_nf_ptr()
+    }
+
+    #[inline]
+    pub fn badge(&self) -> usize {
+        self.0.get_nf_badge()
+    }
+
+    #[inline]
+    pub fn can_send(&self) -> bool {
+        self.0.get_nf_can_send() != 0
+    }
+
+    #[inline]
+    pub fn can_receive(&self) -> bool {
+        self.0.get_nf_can_receive() != 0
+    }
+}
+
+impl ReplyCap {
+    #[inline]
+    pub fn tcb_ptr(&self) -> usize {
+        self.0.get_reply_tcb_ptr()
+    }
+
+    #[inline]
+    pub fn is_master(&self) -> bool {
+        self.0.get_reply_master() != 0
+    }
+
+    #[inline]
+    pub fn can_grant(&self) -> bool {
+        self.0.get_reply_can_grant() != 0
+    }
+}
+
+impl CNodeCap {
+    #[inline]
+    pub fn ptr(&self) -> usize {
+        self.0.get_cnode_ptr()
+    }
+
+    #[inline]
+    pub fn radix(&self) -> usize {
+        self.0.get_cnode_radix()
+    }
+
+    #[inline]
+    pub fn guard(&self) -> usize {
+        self.0.get_cnode_guard()
+    }
+
+    #[inline]
+    pub fn guard_size(&self) -> usize {
+        self.0.get_cnode_guard_size()
+    }
+}
+
+impl ThreadCap {
+    #[inline]
+    pub fn tcb_ptr(&self) -> usize {
+        self.0.get_tcb_ptr()
+    }
+}
+
+impl IrqHandlerCap {
+    #[inline]
+    pub fn irq(&self) -> usize {
+        self.0.get_irq_handler()
+    }
+}
+
+// 只有有效的`zombie`才能得到`ZombieCap`，字段总能解码
+impl ZombieCap {
+    /// The first slot of the object being deleted.
+    #[inline]
+    pub fn ptr(&self) -> usize {
+        self.0.get_zombie_ptr().expect("checked by try_from")
+    }
+
+    /// The number of slots not yet deleted.
+    #[inline]
+    pub fn remaining_slots(&self) -> usize {
+        self.0.get_zombie_number().expect("checked by try_from")
+    }
+
+    #[inline]
+    pub fn kind(&self) -> ZombieKind {
+        self.0.get_zombie_kind().expect("checked by try_from")
+    }
+}
+
+impl FrameCap {
+    #[inline]
+    pub fn base_ptr(&self) -> usize {
+        self.0.get_frame_base_ptr()
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.0.get_frame_size()
+    }
+
+    #[inline]
+    pub fn vm_rights(&self) -> vm_rights_t {
+        vm_rights_t::from_word(self.0.get_frame_vm_rights())
+    }
+
+    #[inline]
+    pub fn is_device(&self) -> bool {
+        self.0.get_frame_is_device() != 0
+    }
+
+    #[inline]
+    pub fn mapped_asid(&self) -> usize {
+        self.0.get_frame_mapped_asid()
+    }
+
+    #[inline]
+    pub fn mapped_address(&self) -> usize {
+        self.0.get_frame_mapped_address()
+    }
+}
+
+impl PageTableCap {
+    #[inline]
+    pub fn base_ptr(&self) -> usize {
+        self.0.get_pt_base_ptr()
+    }
+
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        self.0.get_pt_is_mapped() != 0
+    }
+
+    #[inline]
+    pub fn mapped_asid(&self) -> usize {
+        self.0.get_pt_mapped_asid()
+    }
+
+    #[inline]
+    pub fn mapped_address(&self) -> usize {
+        self.0.get_pt_mapped_address()
+    }
+}
+
+impl ASIDPoolCap {
+    #[inline]
+    pub fn asid_base(&self) -> usize {
+        self.0.get_asid_base()
+    }
+
+    #[inline]
+    pub fn pool(&self) -> usize {
+        self.0.get_asid_pool()
+    }
+}
@@ -2,68 +2,164 @@
 //! 当`tcb_cap`和`cnode_cap`删除的过程中会变为`zombie_cap`
 
 use crate::cte::cte_t;
-use sel4_common::sel4_config::wordRadix;
-use sel4_common::MASK;
+use sel4_common::sel4_config::{seL4_SlotBits, tcbCNodeEntries, wordBits, wordRadix};
+use sel4_common::{BIT, MASK};
 
-use super::{cap_t, CapTag};
+use super::{cap_t, view::ZombieCap};
 
 /// Judge whether the zombie cap is from tcb cap.
 pub const ZombieType_ZombieTCB: usize = 1usize << wordRadix;
 pub const TCB_CNODE_RADIX: usize = 4;
 
+/// The object a zombie cap is deleting, decoded from `capZombieType`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZombieKind {
+    /// The `tcbCNodeEntries` slots of a TCB.
+    Tcb,
+    /// A CNode of `BIT!(radix)` slots.
+    CNode { radix: usize },
+}
 
-impl cap_t {
+impl ZombieKind {
+    /// Decode `capZombieType`, `None` if it is neither a TCB nor a CNode which fits in memory.
     #[inline]
-    pub fn get_zombie_bit(&self) -> usize {
-        let _type = self.get_zombie_type();
-        if _type == ZombieType_ZombieTCB {
-            return TCB_CNODE_RADIX;
+    pub fn from_word(word: usize) -> Option<Self> {
+        if word == ZombieType_ZombieTCB {
+            Some(ZombieKind::Tcb)
+        } else if word + seL4_SlotBits <= wordBits {
+            Some(ZombieKind::CNode { radix: word })
+        } else {
+            None
         }
-        return ZombieType_ZombieCNode(_type);
     }
 
+    /// The value stored in `capZombieType`.
     #[inline]
-    pub fn get_zombie_ptr(&self) -> usize {
-        let radix = self.get_zombie_bit();
-        return self.get_zombie_id() & !MASK!(radix + 1);
+    pub fn to_word(self) -> usize {
+        match self {
+            ZombieKind::Tcb => ZombieType_ZombieTCB,
+            ZombieKind::CNode { radix } => radix,
+        }
     }
 
+    /// The number of slots of the object, the largest number a zombie of this kind can have.
     #[inline]
-    pub fn get_zombie_number(&self) -> usize {
-        let radix = self.get_zombie_bit();
-        return self.get_zombie_id() & MASK!(radix + 1);
+    pub fn slots(self) -> usize {
+        match self {
+            ZombieKind::Tcb => tcbCNodeEntries,
+            ZombieKind::CNode { radix } => BIT!(radix),
+        }
     }
 
+    /// The low bits of `capZombieID` holding the number, the others hold the pointer.
     #[inline]
-    pub fn set_zombie_number(&mut self, n: usize) {
-        let radix = self.get_zombie_bit();
-        let ptr = self.get_zombie_id() & !MASK!(radix + 1);
-        self.set_zombie_id(ptr | (n & MASK!(radix + 1)));
+    fn number_bits(self) -> usize {
+        match self {
+            ZombieKind::Tcb => TCB_CNODE_RADIX + 1,
+            ZombieKind::CNode { radix } => radix + 1,
+        }
     }
 }
 
-///create a new zombie cap
-#[inline]
-pub fn Zombie_new(number: usize, _type: usize, ptr: usize) -> cap_t {
-    let mask: usize;
-    if _type == ZombieType_ZombieTCB {
-        mask = MASK!(TCB_CNODE_RADIX + 1);
-    } else {
-        mask = MASK!(_type + 1);
+/// Why a zombie cap cannot be built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZombieError {
+    /// `capZombieType` is not a valid `ZombieKind`.
+    InvalidType(usize),
+    /// The object has only `slots` slots.
+    NumberOutOfRange { number: usize, slots: usize },
+    /// The pointer overlaps the bits holding the number.
+    MisalignedPtr(usize),
+}
+
+impl ZombieCap {
+    /// Build a zombie cap for the object at `ptr` with `number` slots left to delete.
+    pub fn new(kind: ZombieKind, ptr: usize, number: usize) -> Result<Self, ZombieError> {
+        let mask = MASK!(kind.number_bits());
+        if number > kind.slots() {
+            return Err(ZombieError::NumberOutOfRange {
+                number,
+                slots: kind.slots(),
+            });
+        }
+        if ptr & mask != 0 {
+            return Err(ZombieError::MisalignedPtr(ptr));
+        }
+        let cap = cap_t::new_zombie_cap(ptr | number, kind.to_word());
+        Ok(ZombieCap::try_from(cap).unwrap())
     }
-    return cap_t::new_zombie_cap((ptr & !mask) | (number & mask), _type);
 }
 
-pub fn ZombieType_ZombieCNode(n: usize) -> usize {
-    return n & MASK!(wordRadix);
+impl cap_t {
+    /// The kind of the zombie, `None` if `capZombieType` is invalid.
+    #[inline]
+    pub fn get_zombie_kind(&self) -> Option<ZombieKind> {
+        ZombieKind::from_word(self.get_zombie_type())
+    }
+
+    /// `None` if `capZombieType` is invalid.
+    #[inline]
+    pub fn get_zombie_bit(&self) -> Option<usize> {
+        Some(self.get_zombie_kind()?.number_bits() - 1)
+    }
+
+    /// `None` if `capZombieType` is invalid.
+    #[inline]
+    pub fn get_zombie_ptr(&self) -> Option<usize> {
+        let bits = self.get_zombie_kind()?.number_bits();
+        Some(self.get_zombie_id() & !MASK!(bits))
+    }
+
+    /// `None` if `capZombieType` is invalid or the number is larger than the object.
+    #[inline]
+    pub fn get_zombie_number(&self) -> Option<usize> {
+        let kind = self.get_zombie_kind()?;
+        let number = self.get_zombie_id() & MASK!(kind.number_bits());
+        if number > kind.slots() {
+            None
+        } else {
+            Some(number)
+        }
+    }
+
+    /// Set the number of slots left to delete, the cap is left unchanged if `capZombieType` is
+    /// invalid or the object has fewer slots.
+    #[inline]
+    pub fn set_zombie_number(&mut self, n: usize) -> Result<(), ZombieError> {
+        let word = self.get_zombie_type();
+        let kind = ZombieKind::from_word(word).ok_or(ZombieError::InvalidType(word))?;
+        if n > kind.slots() {
+            return Err(ZombieError::NumberOutOfRange {
+                number: n,
+                slots: kind.slots(),
+            });
+        }
+        let ptr = self.get_zombie_id() & !MASK!(kind.number_bits());
+        self.set_zombie_id(ptr | n);
+        Ok(())
+    }
+
+    /// Whether the zombie has a valid type and number, the zombies `ZombieCap` is a view of.
+    #[inline]
+    pub(crate) fn is_valid_zombie(&self) -> bool {
+        self.get_zombie_number().is_some()
+    }
 }
 
+///create a new zombie cap
+///
+/// Unlike seL4, nothing is masked: an invalid type, a number larger than the object or a pointer
+/// overlapping the number is rejected, as by `ZombieCap::new`.
+#[inline]
+pub fn Zombie_new(number: usize, _type: usize, ptr: usize) -> Result<cap_t, ZombieError> {
+    let kind = ZombieKind::from_word(_type).ok_or(ZombieError::InvalidType(_type))?;
+    Ok(ZombieCap::new(kind, ptr, number)?.into())
+}
 
 ///判断是否为循环`zombie cap`,指向自身且类型为`CapZombieCap`（似乎只有`CNode Capability`指向自己才会出现这种情况）
 /// 根据网上信息，当`cnode cap`为L2以上时，即`CNode`嵌套`CNode`的情况，就会产生`CyclicZombie`
 #[inline]
 #[no_mangle]
 pub fn capCyclicZombie(cap: &cap_t, slot: *mut cte_t) -> bool {
-    let ptr = cap.get_zombie_ptr() as *mut cte_t;
-    (cap.get_cap_type() == CapTag::CapZombieCap) && (ptr == slot)
+    ZombieCap::try_from(*cap).is_ok_and(|zombie| zombie.ptr() as *mut cte_t == slot)
 }
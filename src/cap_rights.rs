@@ -20,3 +20,44 @@ impl seL4_CapRights_t {
         Self { words: [word] }
     }
 }
+
+/// The rights of a frame mapping, stored in `capFVMRights` of a frame cap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum vm_rights_t {
+    VMKernelOnly = 1,
+    VMReadOnly = 2,
+    VMReadWrite = 3,
+}
+
+impl vm_rights_t {
+    #[inline]
+    pub fn from_word(word: usize) -> Self {
+        match word {
+            2 => vm_rights_t::VMReadOnly,
+            3 => vm_rights_t::VMReadWrite,
+            _ => vm_rights_t::VMKernelOnly,
+        }
+    }
+
+    #[inline]
+    pub fn to_word(self) -> usize {
+        self as usize
+    }
+
+    /// Reduce the vm rights to those allowed by `cap_rights_mask`, write only mappings are not supported.
+    pub fn mask(self, cap_rights_mask: &seL4_CapRights_t) -> Self {
+        let allow_read = cap_rights_mask.get_allow_read() != 0;
+        let allow_write = cap_rights_mask.get_allow_write() != 0;
+        match self {
+            vm_rights_t::VMReadOnly if allow_read => vm_rights_t::VMReadOnly,
+            vm_rights_t::VMReadWrite if allow_read => {
+                if allow_write {
+                    vm_rights_t::VMReadWrite
+                } else {
+                    vm_rights_t::VMReadOnly
+                }
+            }
+            _ => vm_rights_t::VMKernelOnly,
+        }
+    }
+}
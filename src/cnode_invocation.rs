@@ -0,0 +1,340 @@
+//! Decoding and performing the invocations of a `CNode` object, corresponding to
+//! `decodeCNodeInvocation` and the `invokeCNode*` functions in seL4.
+//!
+//! The kernel fetches the message words and the caps in the extra cap slots, calls
+//! `decode_cnode_invocation`, sets the thread state to restart and then calls `invoke_cnode`.
+
+use crate::cap::view::EndpointCap;
+use crate::cap::{cap_t, CapTag};
+use crate::cap_rights::seL4_CapRights_t;
+use crate::cte::{convert_to_slot, cte_insert, cte_move, cte_rotate, cte_swap, cte_t};
+use crate::budget::WorkBudget;
+use crate::deps::CSpaceHooks;
+use crate::lookup::{
+    ensure_empty_slot, ensure_non_empty_slot, lookup_pivot_slot, lookup_source_slot,
+    lookup_target_slot,
+};
+use crate::structures::syscall_error_t;
+use core::intrinsics::unlikely;
+use sel4_common::message_info::MessageLabel;
+use sel4_common::sel4_config::{seL4_IllegalOperation, seL4_RevokeFirst, seL4_TruncatedMessage};
+use sel4_common::structures::exception_t;
+
+/// A decoded CNode invocation, all the slots have been looked up and checked.
+#[derive(Clone, Copy, Debug)]
+pub enum CNodeInvocation {
+    Revoke {
+        dest_slot: *mut cte_t,
+    },
+    Delete {
+        dest_slot: *mut cte_t,
+    },
+    CancelBadgedSends {
+        cap: EndpointCap,
+    },
+    /// Copy and Mint: insert `cap` derived from `src_slot` into `dest_slot`.
+    Insert {
+        cap: cap_t,
+        src_slot: *mut cte_t,
+        dest_slot: *mut cte_t,
+    },
+    /// Move and Mutate: move `src_slot` into `dest_slot` with the new `cap`.
+    Move {
+        cap: cap_t,
+        src_slot: *mut cte_t,
+        dest_slot: *mut cte_t,
+    },
+    /// Move `pivot_slot` into `dest_slot` and `src_slot` into `pivot_slot`, swap them when
+    /// `dest_slot` is `src_slot`.
+    Rotate {
+        src_cap: cap_t,
+        pivot_cap: cap_t,
+        src_slot: *mut cte_t,
+        pivot_slot: *mut cte_t,
+        dest_slot: *mut cte_t,
+    },
+    /// Move the reply cap in the `caller_slot` of current thread into `dest_slot`, unless it is the
+    /// reply master.
+    SaveCaller {
+        caller_slot: *mut cte_t,
+        dest_slot: *mut cte_t,
+    },
+}
+
+/// Decode a CNode invocation.
+///
+/// `args` are the message words of the invocation, `extra_caps` are the caps in the extra cap slots
+/// and `caller_slot` is the `tcbCaller` slot of current thread.
+pub fn decode_cnode_invocation(
+    label: MessageLabel,
+    cap: &cap_t,
+    args: &[usize],
+    extra_caps: &[cap_t],
+    caller_slot: *mut cte_t,
+) -> Result<CNodeInvocation, syscall_error_t> {
+    if label < MessageLabel::CNodeRevoke || label > MessageLabel::CNodeSaveCaller {
+        return Err(syscall_error_t::new(seL4_IllegalOperation));
+    }
+    if args.len() < 2 {
+        return Err(syscall_error_t::new(seL4_TruncatedMessage));
+    }
+    let index = args[0];
+    let w_bits = args[1];
+    let lu_ret = lookup_target_slot(cap, index, w_bits);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return Err(lu_ret.error);
+    }
+    let dest_slot = lu_ret.slot;
+
+    if label >= MessageLabel::CNodeCopy && label <= MessageLabel::CNodeMutate {
+        if args.len() < 4 || extra_caps.is_empty() {
+            return Err(syscall_error_t::new(seL4_TruncatedMessage));
+        }
+        let src_index = args[2];
+        let src_depth = args[3];
+        let src_root = extra_caps[0];
+        let status = ensure_empty_slot(convert_to_slot(dest_slot));
+        if status.status != exception_t::EXCEPTION_NONE {
+            return Err(status.error);
+        }
+        let lu_ret = lookup_source_slot(&src_root, src_index, src_depth);
+        if lu_ret.status != exception_t::EXCEPTION_NONE {
+            return Err(lu_ret.error);
+        }
+        let lu_ret = ensure_non_empty_slot(true, convert_to_slot(lu_ret.slot), src_depth);
+        if lu_ret.status != exception_t::EXCEPTION_NONE {
+            return Err(lu_ret.error);
+        }
+        let src_slot = convert_to_slot(lu_ret.slot);
+        let (new_cap, is_move) = match label {
+            MessageLabel::CNodeCopy => {
+                if args.len() < 5 {
+                    return Err(syscall_error_t::new(seL4_TruncatedMessage));
+                }
+                let cap_rights = seL4_CapRights_t::from_word(args[4]);
+                let src_cap = src_slot.cap.mask_rights(cap_rights);
+                (derive_cap(src_slot, &src_cap)?, false)
+            }
+            MessageLabel::CNodeMint => {
+                if args.len() < 6 {
+                    return Err(syscall_error_t::new(seL4_TruncatedMessage));
+                }
+                let cap_rights = seL4_CapRights_t::from_word(args[4]);
+                let cap_data = args[5];
+                let src_cap = src_slot.cap.mask_rights(cap_rights);
+                (
+                    derive_cap(src_slot, &src_cap.update_data(false, cap_data))?,
+                    false,
+                )
+            }
+            MessageLabel::CNodeMove => (src_slot.cap, true),
+            _ => {
+                if args.len() < 5 {
+                    return Err(syscall_error_t::new(seL4_TruncatedMessage));
+                }
+                let cap_data = args[4];
+                (src_slot.cap.update_data(true, cap_data), true)
+            }
+        };
+        if new_cap.cap_type_or_null() == CapTag::CapNullCap {
+            return Err(syscall_error_t::new(seL4_IllegalOperation));
+        }
+        let src_slot = src_slot as *mut cte_t;
+        if is_move {
+            return Ok(CNodeInvocation::Move {
+                cap: new_cap,
+                src_slot,
+                dest_slot,
+            });
+        }
+        return Ok(CNodeInvocation::Insert {
+            cap: new_cap,
+            src_slot,
+            dest_slot,
+        });
+    }
+
+    match label {
+        MessageLabel::CNodeRevoke => Ok(CNodeInvocation::Revoke { dest_slot }),
+        MessageLabel::CNodeDelete => Ok(CNodeInvocation::Delete { dest_slot }),
+        MessageLabel::CNodeSaveCaller => {
+            let status = ensure_empty_slot(convert_to_slot(dest_slot));
+            if status.status != exception_t::EXCEPTION_NONE {
+                return Err(status.error);
+            }
+            Ok(CNodeInvocation::SaveCaller {
+                caller_slot,
+                dest_slot,
+            })
+        }
+        MessageLabel::CNodeCancelBadgedSends => {
+            match EndpointCap::try_from(convert_to_slot(dest_slot).cap) {
+                Ok(cap) if has_cancel_send_rights(&cap) => {
+                    Ok(CNodeInvocation::CancelBadgedSends { cap })
+                }
+                _ => Err(syscall_error_t::new(seL4_IllegalOperation)),
+            }
+        }
+        _ => decode_cnode_rotate(args, extra_caps, dest_slot),
+    }
+}
+
+fn decode_cnode_rotate(
+    args: &[usize],
+    extra_caps: &[cap_t],
+    dest_slot: *mut cte_t,
+) -> Result<CNodeInvocation, syscall_error_t> {
+    if args.len() < 8 || extra_caps.len() < 2 {
+        return Err(syscall_error_t::new(seL4_TruncatedMessage));
+    }
+    let pivot_new_data = args[2];
+    let pivot_index = args[3];
+    let pivot_depth = args[4];
+    let src_new_data = args[5];
+    let src_index = args[6];
+    let src_depth = args[7];
+    let pivot_root = extra_caps[0];
+    let src_root = extra_caps[1];
+
+    let lu_ret = lookup_source_slot(&src_root, src_index, src_depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return Err(lu_ret.error);
+    }
+    let src_slot = lu_ret.slot;
+    let lu_ret = lookup_pivot_slot(&pivot_root, pivot_index, pivot_depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return Err(lu_ret.error);
+    }
+    let pivot_slot = lu_ret.slot;
+
+    if pivot_slot == src_slot || pivot_slot == dest_slot {
+        return Err(syscall_error_t::new(seL4_IllegalOperation));
+    }
+    if src_slot != dest_slot {
+        let status = ensure_empty_slot(convert_to_slot(dest_slot));
+        if status.status != exception_t::EXCEPTION_NONE {
+            return Err(status.error);
+        }
+    }
+    let lu_ret = ensure_non_empty_slot(true, convert_to_slot(src_slot), src_depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return Err(lu_ret.error);
+    }
+    let lu_ret = ensure_non_empty_slot(false, convert_to_slot(pivot_slot), pivot_depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return Err(lu_ret.error);
+    }
+
+    let src_cap = convert_to_slot(src_slot)
+        .cap
+        .update_data(true, src_new_data);
+    let pivot_cap = convert_to_slot(pivot_slot)
+        .cap
+        .update_data(true, pivot_new_data);
+    if src_cap.cap_type_or_null() == CapTag::CapNullCap
+        || pivot_cap.cap_type_or_null() == CapTag::CapNullCap
+    {
+        return Err(syscall_error_t::new(seL4_IllegalOperation));
+    }
+    Ok(CNodeInvocation::Rotate {
+        src_cap,
+        pivot_cap,
+        src_slot,
+        pivot_slot,
+        dest_slot,
+    })
+}
+
+/// Perform a decoded CNode invocation, the rest of the kernel is reached through `hooks`, and
+/// revoke and delete are preempted when `budget` is exhausted.
+pub fn invoke_cnode<H: CSpaceHooks, B: WorkBudget>(
+    invocation: CNodeInvocation,
+    hooks: &mut H,
+    budget: &mut B,
+) -> exception_t {
+    match invocation {
+        CNodeInvocation::Revoke { dest_slot } => {
+            convert_to_slot(dest_slot).revoke_with(hooks, budget)
+        }
+        CNodeInvocation::Delete { dest_slot } => {
+            convert_to_slot(dest_slot).delete_all_with(true, hooks, budget)
+        }
+        CNodeInvocation::CancelBadgedSends { cap } => {
+            let badge = cap.badge();
+            if badge != 0 {
+                hooks.cancel_badged_sends(cap.ptr(), badge);
+            }
+            exception_t::EXCEPTION_NONE
+        }
+        CNodeInvocation::Insert {
+            cap,
+            src_slot,
+            dest_slot,
+        } => {
+            cte_insert(&cap, convert_to_slot(src_slot), convert_to_slot(dest_slot));
+            exception_t::EXCEPTION_NONE
+        }
+        CNodeInvocation::Move {
+            cap,
+            src_slot,
+            dest_slot,
+        } => {
+            cte_move(&cap, convert_to_slot(src_slot), convert_to_slot(dest_slot));
+            exception_t::EXCEPTION_NONE
+        }
+        CNodeInvocation::Rotate {
+            src_cap,
+            pivot_cap,
+            src_slot,
+            pivot_slot,
+            dest_slot,
+        } => {
+            let (src, pivot) = (convert_to_slot(src_slot), convert_to_slot(pivot_slot));
+            if src_slot == dest_slot {
+                cte_swap(&src_cap, src, &pivot_cap, pivot);
+            } else {
+                cte_rotate(&src_cap, src, &pivot_cap, pivot, convert_to_slot(dest_slot));
+            }
+            exception_t::EXCEPTION_NONE
+        }
+        CNodeInvocation::SaveCaller {
+            caller_slot,
+            dest_slot,
+        } => {
+            let caller_slot = convert_to_slot(caller_slot);
+            let cap = caller_slot.cap;
+            match cap.cap_type_or_null() {
+                // Reply cap not present, nothing to save.
+                CapTag::CapNullCap => {}
+                // The reply master stays in the caller slot, as in seL4.
+                CapTag::CapReplyCap => {
+                    if cap.get_reply_master() == 0 {
+                        cte_move(&cap, caller_slot, convert_to_slot(dest_slot));
+                    }
+                }
+                _ => {
+                    panic!("caller capability must be null or reply");
+                }
+            }
+            exception_t::EXCEPTION_NONE
+        }
+    }
+}
+
+/// CancelBadgedSends needs all the rights of an endpoint cap.
+fn has_cancel_send_rights(cap: &EndpointCap) -> bool {
+    cap.can_send() && cap.can_receive() && cap.can_grant() && cap.can_grant_reply()
+}
+
+/// `cte_t::derive_cap` with the syscall error filled in as seL4 does.
+fn derive_cap(src_slot: &mut cte_t, cap: &cap_t) -> Result<cap_t, syscall_error_t> {
+    let dc_ret = src_slot.derive_cap(cap);
+    if unlikely(dc_ret.status != exception_t::EXCEPTION_NONE) {
+        // An untyped cap with children must be revoked first, other failures are illegal.
+        if cap.cap_type_or_null() == CapTag::CapUntypedCap {
+            return Err(syscall_error_t::new(seL4_RevokeFirst));
+        }
+        return Err(syscall_error_t::new(seL4_IllegalOperation));
+    }
+    Ok(dc_ret.cap)
+}
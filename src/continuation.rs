@@ -0,0 +1,242 @@
+//! Revoke and delete as resumable operations.
+//!
+//! `cte_t::revoke` and `cte_t::delete_all` return `EXCEPTION_PREEMTED` from a preemption point,
+//! and the kernel restarts the whole syscall, decoding and looking up the slot again. A
+//! `CSpaceContinuation` keeps the operation instead, so it can be resumed directly, and it records
+//! the progress made across all its runs.
+//!
+//! ```ignore
+//! let mut op = CSpaceContinuation::revoke(slot);
+//! while op.resume(&mut hooks, &mut budget) == exception_t::EXCEPTION_PREEMTED {
+//!     // handle the pending interrupt
+//! }
+//! ```
+//!
+//! Resuming does not lose or repeat any work: the descendants deleted by a revoke are gone from
+//! the MDB list, so the next run starts at the next descendant of the slot, where the last one
+//! stopped, and a slot being finalised keeps its zombie, which the next run continues to reduce.
+//!
+//! Other operations may run between two resumes, so the slot is checked first: if it no longer
+//! holds the cap it held when the operation was created or last preempted, the operation is
+//! abandoned instead of being run on a different cap.
+
+use crate::budget::{Preempted, WorkBudget};
+use crate::cap::cap_t;
+use crate::cte::cte_t;
+use crate::deps::CSpaceHooks;
+use crate::structures::finaliseCap_ret;
+use sel4_common::object::ObjectType;
+use sel4_common::structures::exception_t;
+use sel4_common::utils::convert_to_mut_type_ref;
+
+/// The operation carried by a `CSpaceContinuation`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CSpaceOperation {
+    /// Delete all the descendants of the slot.
+    Revoke { slot: usize },
+    /// Delete the cap in the slot, `exposed` as in `cte_t::delete_all`.
+    Delete { slot: usize, exposed: bool },
+}
+
+impl CSpaceOperation {
+    #[inline]
+    fn slot(&self) -> usize {
+        match *self {
+            CSpaceOperation::Revoke { slot } | CSpaceOperation::Delete { slot, .. } => slot,
+        }
+    }
+}
+
+/// A revoke or delete which can be resumed after being preempted.
+#[derive(Clone, Copy, Debug)]
+pub struct CSpaceContinuation {
+    operation: CSpaceOperation,
+    done: bool,
+    abandoned: bool,
+    runs: usize,
+    work_units: usize,
+    deleted: usize,
+    /// The cap in the slot when the operation was created or last preempted.
+    cap: cap_t,
+    stopped_at: usize,
+}
+
+impl CSpaceContinuation {
+    fn new(operation: CSpaceOperation) -> Self {
+        CSpaceContinuation {
+            operation,
+            done: false,
+            abandoned: false,
+            runs: 0,
+            work_units: 0,
+            deleted: 0,
+            cap: convert_to_mut_type_ref::<cte_t>(operation.slot()).cap,
+            stopped_at: 0,
+        }
+    }
+
+    /// Revoke the cap in `slot`.
+    pub fn revoke(slot: *mut cte_t) -> Self {
+        Self::new(CSpaceOperation::Revoke {
+            slot: slot as usize,
+        })
+    }
+
+    /// Delete the cap in `slot`.
+    pub fn delete(slot: *mut cte_t, exposed: bool) -> Self {
+        Self::new(CSpaceOperation::Delete {
+            slot: slot as usize,
+            exposed,
+        })
+    }
+
+    /// Run the operation until it completes or `budget` is exhausted, and return its status.
+    ///
+    /// If the slot no longer holds the cap it held when the operation was created or last
+    /// preempted, nothing is run, the operation is abandoned and `EXCEPTION_SYSCALL_ERROR` is
+    /// returned. After the operation completed or was abandoned, resuming it again does nothing
+    /// and returns `EXCEPTION_NONE`.
+    pub fn resume<H: CSpaceHooks, B: WorkBudget>(
+        &mut self,
+        hooks: &mut H,
+        budget: &mut B,
+    ) -> exception_t {
+        if self.done {
+            return exception_t::EXCEPTION_NONE;
+        }
+        let slot = convert_to_mut_type_ref::<cte_t>(self.operation.slot());
+        if slot.cap.words != self.cap.words {
+            self.done = true;
+            self.abandoned = true;
+            self.stopped_at = 0;
+            return exception_t::EXCEPTION_SYSCALL_ERROR;
+        }
+        self.runs += 1;
+        let mut hooks = CountingHooks {
+            inner: hooks,
+            deleted: 0,
+        };
+        let mut budget = CountingBudget {
+            inner: budget,
+            work_units: 0,
+        };
+        // 已删除的子孙不在链表中，`revoke_with`从`slot`的下一个节点继续，即上次停下的位置
+        let status = match self.operation {
+            CSpaceOperation::Revoke { .. } => slot.revoke_with(&mut hooks, &mut budget),
+            CSpaceOperation::Delete { exposed, .. } => {
+                slot.delete_all_with(exposed, &mut hooks, &mut budget)
+            }
+        };
+        self.work_units += budget.work_units;
+        self.deleted += hooks.deleted;
+        if status == exception_t::EXCEPTION_PREEMTED {
+            // 删除时`slot`中的`cap`变为`zombie`，下次继续前与它比较
+            self.cap = slot.cap;
+            self.stopped_at = match self.operation {
+                CSpaceOperation::Revoke { .. } => slot.cteMDBNode.get_next(),
+                CSpaceOperation::Delete { slot, .. } => slot,
+            };
+        } else {
+            self.done = true;
+            self.stopped_at = 0;
+        }
+        status
+    }
+
+    #[inline]
+    pub fn operation(&self) -> CSpaceOperation {
+        self.operation
+    }
+
+    /// Whether the operation has completed, successfully or not, or was abandoned.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Whether the operation was abandoned because the cap in its slot changed between two runs.
+    #[inline]
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned
+    }
+
+    /// The number of times the operation has been run.
+    #[inline]
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    /// The number of work units charged, over all the runs.
+    #[inline]
+    pub fn work_units(&self) -> usize {
+        self.work_units
+    }
+
+    /// The number of caps deleted, over all the runs, counted by the calls to
+    /// `CSpaceHooks::post_cap_deletion`.
+    #[inline]
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+
+    /// Where the operation was last preempted: for a revoke, the descendant being deleted or to be
+    /// deleted next, for a delete, the slot itself, holding the zombie being reduced. `None` when
+    /// it is not preempted.
+    #[inline]
+    pub fn stopped_at(&self) -> Option<*mut cte_t> {
+        if self.stopped_at == 0 {
+            None
+        } else {
+            Some(self.stopped_at as *mut cte_t)
+        }
+    }
+}
+
+/// 统计`post_cap_deletion`的调用次数，即删除的`cap`数量
+struct CountingHooks<'a, H: CSpaceHooks> {
+    inner: &'a mut H,
+    deleted: usize,
+}
+
+impl<H: CSpaceHooks> CSpaceHooks for CountingHooks<'_, H> {
+    fn finalise_cap(&mut self, cap: &cap_t, final_: bool, exposed: bool) -> finaliseCap_ret {
+        self.inner.finalise_cap(cap, final_, exposed)
+    }
+
+    fn post_cap_deletion(&mut self, cap: &cap_t) {
+        self.deleted += 1;
+        self.inner.post_cap_deletion(cap)
+    }
+
+    fn cancel_badged_sends(&mut self, epptr: usize, badge: usize) {
+        self.inner.cancel_badged_sends(epptr, badge)
+    }
+
+    fn init_object(
+        &mut self,
+        object_type: ObjectType,
+        ptr: usize,
+        user_size: usize,
+        device_memory: bool,
+    ) {
+        self.inner
+            .init_object(object_type, ptr, user_size, device_memory)
+    }
+
+    fn clear_memory(&mut self, ptr: usize, size_bits: usize) {
+        self.inner.clear_memory(ptr, size_bits)
+    }
+}
+
+/// 统计记到`budget`上的工作量
+struct CountingBudget<'a, B: WorkBudget> {
+    inner: &'a mut B,
+    work_units: usize,
+}
+
+impl<B: WorkBudget> WorkBudget for CountingBudget<'_, B> {
+    fn charge(&mut self, units: usize) -> Result<(), Preempted> {
+        self.work_units += units;
+        self.inner.charge(units)
+    }
+}
@@ -2,15 +2,22 @@
 
 use super::{
     cap::{cap_t, is_cap_revocable, same_object_as, same_region_as, CapTag},
-    deps::{finaliseCap, post_cap_deletion, preemptionPoint},
+    budget::WorkBudget,
+    deps::CSpaceHooks,
     mdb::mdb_node_t,
     structures::{finaliseSlot_ret, resolveAddressBits_ret_t},
 };
+use crate::cap::view::{EndpointCap, NotificationCap, UntypedCap, ZombieCap};
 use crate::cap::zombie::capCyclicZombie;
+#[cfg(feature = "debug_mdb")]
+use crate::mdb::assert_invariants;
+#[cfg(feature = "extern_deps")]
+use crate::{budget::KernelPreemptionPoint, deps::ExternHooks};
 use core::intrinsics::{likely, unlikely};
 use core::ptr;
 use sel4_common::utils::{convert_to_option_mut_type_ref, MAX_FREE_INDEX};
 use sel4_common::{
+    fault::lookup_fault_t,
     sel4_config::wordRadix,
     structures::exception_t,
     utils::{convert_to_mut_type_ref, convert_to_type_ref},
@@ -25,6 +32,38 @@ pub struct deriveCap_ret {
     pub cap: cap_t,
 }
 
+/// The most frames `cte_t::finalise` uses: the slot being finalised and the last slot of its zombie.
+pub const MAX_DELETE_DEPTH: usize = 2;
+
+/// A slot being finalised by `cte_t::finalise`.
+#[derive(Clone, Copy)]
+struct DeleteFrame {
+    slot: *mut cte_t,
+    immediate: bool,
+    /// The zombie in the slot while the last slot of it is being deleted.
+    zombie: Option<ZombieCap>,
+}
+
+impl DeleteFrame {
+    fn new(slot: *mut cte_t, immediate: bool) -> Self {
+        DeleteFrame {
+            slot,
+            immediate,
+            zombie: None,
+        }
+    }
+}
+
+/// What a step of `cte_t::finalise` has done.
+enum FinaliseStep {
+    /// The slot is finalised, or has become a cyclic zombie.
+    Done(finaliseSlot_ret),
+    /// The last slot of the zombie in the slot must be deleted.
+    Nested(*mut cte_t, ZombieCap),
+    /// The zombie has been swapped into its first slot.
+    Swapped,
+}
+
 /// capability table entry, composed by cap and mdb.
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
@@ -51,8 +90,9 @@ impl cte_t {
             cap: cap_t::default(),
         };
 
-        match cap.get_cap_type() {
-            CapTag::CapZombieCap => {
+        match cap.cap_type_or_null() {
+            // 未知类型的`cap`也作为空`cap`，不会被派生
+            CapTag::CapNullCap | CapTag::CapZombieCap => {
                 ret.cap = cap_t::new_null_cap();
             }
             /// `UntypedCap`只允许不存在子节点的能力进行派生
@@ -83,7 +123,7 @@ impl cte_t {
             status: exception_t::EXCEPTION_NONE,
             cap: cap_t::default(),
         };
-        match cap.get_cap_type() {
+        match cap.cap_type_or_null() {
             /// 只允许被`mapped`进行派生
             CapTag::CapPageTableCap => {
                 if cap.get_pt_is_mapped() != 0 {
@@ -104,7 +144,8 @@ impl cte_t {
                 ret.cap = cap.clone();
             }
             _ => {
-                panic!(" Invalid arch cap type : {}", cap.get_cap_type() as usize);
+                ret.cap = cap_t::new_null_cap();
+                ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
             }
         }
         ret
@@ -121,7 +162,7 @@ impl cte_t {
         return exception_t::EXCEPTION_NONE;
     }
     /// 判断当前`cte`是否为`next`节点的父节点（除了父节点，还有兄弟节点的关系可能）
-    fn is_mdb_parent_of(&self, next: &Self) -> bool {
+    pub fn is_mdb_parent_of(&self, next: &Self) -> bool {
         if !(self.cteMDBNode.get_revocable() != 0) {
             return false;
         }
@@ -129,27 +170,25 @@ impl cte_t {
             return false;
         }
 
-        match self.cap.get_cap_type() {
-            CapTag::CapEndpointCap => {
-                assert_eq!(next.cap.get_cap_type(), CapTag::CapEndpointCap);
-                let badge = self.cap.get_ep_badge();
-                if badge == 0 {
-                    return true;
-                }
-                return badge == next.cap.get_ep_badge()
-                    && !(next.cteMDBNode.get_first_badged() != 0);
+        if let Ok(ep) = EndpointCap::try_from(self.cap) {
+            let next_ep =
+                EndpointCap::try_from(next.cap).expect("endpoint region shared by other cap");
+            let badge = ep.badge();
+            if badge == 0 {
+                return true;
             }
-            CapTag::CapNotificationCap => {
-                assert_eq!(next.cap.get_cap_type(), CapTag::CapNotificationCap);
-                let badge = self.cap.get_nf_badge();
-                if badge == 0 {
-                    return true;
-                }
-                return badge == next.cap.get_nf_badge()
-                    && !(next.cteMDBNode.get_first_badged() != 0);
+            return badge == next_ep.badge() && !(next.cteMDBNode.get_first_badged() != 0);
+        }
+        if let Ok(ntfn) = NotificationCap::try_from(self.cap) {
+            let next_ntfn = NotificationCap::try_from(next.cap)
+                .expect("notification region shared by other cap");
+            let badge = ntfn.badge();
+            if badge == 0 {
+                return true;
             }
-            _ => true,
+            return badge == next_ntfn.badge() && !(next.cteMDBNode.get_first_badged() != 0);
         }
+        true
     }
     /// 判断当前`cte`是否是能力派生树上的最后一个能力,如果`prev`与当前指向对象，则当前`cte`不是最后一个`cap`
     /// 如果`cte`的`next`是当前`cte`派生出来的能力，则当前`cte`也不是最后一个`cap`
@@ -175,10 +214,10 @@ impl cte_t {
     }
 
     pub fn is_long_running_delete(&self) -> bool {
-        if self.cap.get_cap_type() == CapTag::CapNullCap || !self.is_final_cap() {
+        if self.cap.cap_type_or_null() == CapTag::CapNullCap || !self.is_final_cap() {
             return false;
         }
-        match self.cap.get_cap_type() {
+        match self.cap.cap_type_or_null() {
             CapTag::CapThreadCap | CapTag::CapZombieCap | CapTag::CapCNodeCap => true,
             _ => false,
         }
@@ -192,69 +231,131 @@ impl cte_t {
     /// 之后再次进入`reduce_zombie(false)`，在其中进入`else`分支，
     /// 执行`cteswap`将二级`cnode_cap`中的第一个`cap`与二级`cnode_cap`进行交换，使得二级`cnode_cap`指向自身，变成`cyclicZombie`。
     /// 然后继续清除即可。至于二级`cnode_cap`其实无法被清除。
-    unsafe fn finalise(&mut self, immediate: bool) -> finaliseSlot_ret {
-        let mut ret = finaliseSlot_ret::default();
-        while self.cap.get_cap_type() != CapTag::CapNullCap {
-            let fc_ret = finaliseCap(&self.cap, self.is_final_cap(), false);
-            if cap_removable(&fc_ret.remainder, self) {
-                ret.status = exception_t::EXCEPTION_NONE;
-                ret.success = true;
-                ret.cleanupInfo = fc_ret.cleanupInfo;
-                return ret;
-            }
-            self.cap = fc_ret.remainder;
-            if !immediate && capCyclicZombie(&fc_ret.remainder, self) {
-                ret.status = exception_t::EXCEPTION_NONE;
-                ret.success = false;
-                ret.cleanupInfo = fc_ret.cleanupInfo;
-                return ret;
+    ///
+    /// The recursion above is run with an explicit work list instead of function calls: the
+    /// first frame is the slot being finalised, the second the last slot of its zombie, deleted
+    /// with `delete_all(false)`. The nested deletion is never immediate, it swaps a deeper zombie
+    /// into its own first slot instead of going down, so there are at most `MAX_DELETE_DEPTH`
+    /// frames whatever the nesting of CNodes and TCBs, and the stack usage of deletion is constant.
+    fn finalise<H: CSpaceHooks, B: WorkBudget>(
+        &mut self,
+        immediate: bool,
+        hooks: &mut H,
+        budget: &mut B,
+    ) -> finaliseSlot_ret {
+        let preempted = finaliseSlot_ret {
+            status: exception_t::EXCEPTION_PREEMTED,
+            success: false,
+            cleanupInfo: cap_t::new_null_cap(),
+        };
+        let mut work = [DeleteFrame::new(self, immediate); MAX_DELETE_DEPTH];
+        let mut depth = 1;
+        loop {
+            let frame = work[depth - 1];
+            let slot = convert_to_mut_type_ref::<cte_t>(frame.slot as usize);
+            match slot.finalise_step(frame.immediate, hooks) {
+                FinaliseStep::Done(ret) => {
+                    if depth == 1 {
+                        return ret;
+                    }
+                    // 内层帧结束，即`end_slot.delete_all(false)`返回
+                    if ret.success {
+                        slot.set_empty(&ret.cleanupInfo, hooks);
+                    }
+                    depth -= 1;
+                    let outer = work[depth - 1];
+                    if let Some(zombie) = outer.zombie {
+                        convert_to_mut_type_ref::<cte_t>(outer.slot as usize)
+                            .zombie_reduced(&zombie, slot);
+                    }
+                }
+                FinaliseStep::Nested(end_slot, zombie) => {
+                    assert!(depth < MAX_DELETE_DEPTH);
+                    work[depth - 1].zombie = Some(zombie);
+                    work[depth] = DeleteFrame::new(end_slot, false);
+                    depth += 1;
+                    continue;
+                }
+                FinaliseStep::Swapped => {}
             }
-            let status = self.reduce_zombie(immediate);
-            if exception_t::EXCEPTION_NONE != status {
-                ret.status = status;
-                ret.success = false;
-                ret.cleanupInfo = cap_t::new_null_cap();
-                return ret;
+            if budget.charge(1).is_err() {
+                return preempted;
             }
+        }
+    }
 
-            let status = preemptionPoint();
-            if exception_t::EXCEPTION_NONE != status {
-                ret.status = status;
-                ret.success = false;
-                ret.cleanupInfo = cap_t::new_null_cap();
-                return ret;
+    /// `finalise`循环中的一步：调用`finaliseCap`，然后结束或者开始削减得到的`zombie`
+    fn finalise_step<H: CSpaceHooks>(&mut self, immediate: bool, hooks: &mut H) -> FinaliseStep {
+        let mut ret = finaliseSlot_ret::default();
+        if self.cap.cap_type_or_null() == CapTag::CapNullCap {
+            return FinaliseStep::Done(ret);
+        }
+        let fc_ret = hooks.finalise_cap(&self.cap, self.is_final_cap(), false);
+        let zombie = match zombie_to_reduce(&fc_ret.remainder, self) {
+            Some(zombie) => zombie,
+            None => {
+                ret.success = true;
+                ret.cleanupInfo = fc_ret.cleanupInfo;
+                return FinaliseStep::Done(ret);
             }
+        };
+        slot_cap_changed(self, &self.cap, &fc_ret.remainder);
+        self.cap = fc_ret.remainder;
+        if !immediate && capCyclicZombie(&fc_ret.remainder, self) {
+            ret.success = false;
+            ret.cleanupInfo = fc_ret.cleanupInfo;
+            return FinaliseStep::Done(ret);
         }
-        ret
+        self.reduce_zombie(&zombie, immediate)
     }
 
     /// 将当前的`cte slot`中的能力清除，因为可能是`cnode_cap`或者`tcb_cap`，其中都可以存储多个`cap`，
     /// 所以可能顺带将存储的`cap`也清除掉
+    #[cfg(feature = "extern_deps")]
+    #[inline]
     pub fn delete_all(&mut self, exposed: bool) -> exception_t {
-        let fs_ret = unsafe { self.finalise(exposed) };
+        self.delete_all_with(exposed, &mut ExternHooks, &mut KernelPreemptionPoint)
+    }
+
+    /// 与`delete_all`相同，但通过`hooks`调用内核的其它部分，工作量记在`budget`上
+    pub fn delete_all_with<H: CSpaceHooks, B: WorkBudget>(
+        &mut self,
+        exposed: bool,
+        hooks: &mut H,
+        budget: &mut B,
+    ) -> exception_t {
+        let fs_ret = self.finalise(exposed, hooks, budget);
         if fs_ret.status != exception_t::EXCEPTION_NONE {
             return fs_ret.status;
         }
         if exposed || fs_ret.success {
-            self.set_empty(&fs_ret.cleanupInfo);
+            self.set_empty(&fs_ret.cleanupInfo, hooks);
         }
         return exception_t::EXCEPTION_NONE;
     }
+
     /// 将当前的`cte slot`中的能力清除,要求`cap`是可删除的
+    #[cfg(feature = "extern_deps")]
+    #[inline]
     pub fn delete_one(&mut self) {
-        if self.cap.get_cap_type() != CapTag::CapNullCap {
-            let fc_ret = unsafe { finaliseCap(&self.cap, self.is_final_cap(), true) };
+        self.delete_one_with(&mut ExternHooks)
+    }
+
+    /// 与`delete_one`相同，但通过`hooks`调用内核的其它部分
+    pub fn delete_one_with<H: CSpaceHooks>(&mut self, hooks: &mut H) {
+        if self.cap.cap_type_or_null() != CapTag::CapNullCap {
+            let fc_ret = hooks.finalise_cap(&self.cap, self.is_final_cap(), true);
             assert!(
                 cap_removable(&fc_ret.remainder, self)
-                    && fc_ret.cleanupInfo.get_cap_type() == CapTag::CapNullCap
+                    && fc_ret.cleanupInfo.cap_type_or_null() == CapTag::CapNullCap
             );
-            self.set_empty(&cap_t::new_null_cap());
+            self.set_empty(&cap_t::new_null_cap(), hooks);
         }
     }
 
     /// 将当前`slot`从`capability derivation tree`中删除
-    fn set_empty(&mut self, cleanup_info: &cap_t) {
-        if self.cap.get_cap_type() != CapTag::CapNullCap {
+    fn set_empty<H: CSpaceHooks>(&mut self, cleanup_info: &cap_t, hooks: &mut H) {
+        if self.cap.cap_type_or_null() != CapTag::CapNullCap {
             let mdb_node = self.cteMDBNode;
             let prev_addr = mdb_node.get_prev();
             let next_addr = mdb_node.get_next();
@@ -271,53 +372,58 @@ impl cte_t {
                     as usize;
                 next_node.cteMDBNode.set_first_badged(first_badged);
             }
+            slot_cap_changed(self, &self.cap, &cap_t::new_null_cap());
             self.cap = cap_t::new_null_cap();
             self.cteMDBNode = mdb_node_t::default();
-            unsafe { post_cap_deletion(cleanup_info) };
+            #[cfg(feature = "debug_mdb")]
+            assert_invariants(&[prev_addr as *const cte_t, next_addr as *const cte_t]);
+            hooks.post_cap_deletion(cleanup_info);
         }
     }
     /// 每次删除`zombie cap`中的最后一个`capability`,用于删除unremovable的capability。
-    fn reduce_zombie(&mut self, immediate: bool) -> exception_t {
-        assert_eq!(self.cap.get_cap_type(), CapTag::CapZombieCap);
+    ///
+    /// When immediate, the last slot is returned to be deleted by `finalise`, which then calls
+    /// `zombie_reduced`. Otherwise the zombie is swapped into its first slot.
+    fn reduce_zombie(&mut self, zombie: &ZombieCap, immediate: bool) -> FinaliseStep {
         let self_ptr = self as *mut cte_t as usize;
-        let ptr = self.cap.get_zombie_ptr();
-        let n = self.cap.get_zombie_number();
-        let zombie_type = self.cap.get_zombie_type();
+        let ptr = zombie.ptr();
+        let n = zombie.remaining_slots();
         assert!(n > 0);
         if immediate {
-            let end_slot = unsafe { &mut *((ptr as *mut cte_t).add(n - 1)) };
-            let status = end_slot.delete_all(false);
-            if status != exception_t::EXCEPTION_NONE {
-                return status;
-            }
-            match self.cap.get_cap_type() {
-                CapTag::CapNullCap => {
-                    return exception_t::EXCEPTION_NONE;
-                }
-                CapTag::CapZombieCap => {
-                    let ptr2 = self.cap.get_zombie_ptr();
-                    if ptr == ptr2
-                        && self.cap.get_zombie_number() == n
-                        && self.cap.get_zombie_type() == zombie_type
-                    {
-                        assert_eq!(end_slot.cap.get_cap_type(), CapTag::CapNullCap);
-                        self.cap.set_zombie_number(n - 1);
-                    } else {
-                        assert!(ptr2 == self_ptr && ptr != self_ptr);
-                    }
-                }
-                _ => {
-                    panic!("Expected recursion to result in Zombie.")
-                }
-            }
+            FinaliseStep::Nested(unsafe { (ptr as *mut cte_t).add(n - 1) }, *zombie)
         } else {
             assert_ne!(ptr, self_ptr);
             let next_slot = convert_to_mut_type_ref::<cte_t>(ptr);
             let cap1 = next_slot.cap;
             let cap2 = self.cap;
             cte_swap(&cap1, next_slot, &cap2, self);
+            FinaliseStep::Swapped
+        }
+    }
+
+    /// 最后一个`slot`删除后，更新`zombie`中剩余的数量，`zombie`为删除前的`zombie cap`
+    fn zombie_reduced(&mut self, zombie: &ZombieCap, end_slot: &cte_t) {
+        let self_ptr = self as *mut cte_t as usize;
+        let (ptr, n) = (zombie.ptr(), zombie.remaining_slots());
+        match ZombieCap::try_from(self.cap) {
+            Ok(current) => {
+                let ptr2 = current.ptr();
+                if ptr == ptr2 && current.remaining_slots() == n && current.kind() == zombie.kind()
+                {
+                    assert_eq!(end_slot.cap.cap_type_or_null(), CapTag::CapNullCap);
+                    // `n - 1`不超过`slot`数，不会失败
+                    let _ = self.cap.set_zombie_number(n - 1);
+                } else {
+                    assert!(ptr2 == self_ptr && ptr != self_ptr);
+                }
+            }
+            Err(cap) if cap.cap_type_or_null() == CapTag::CapNullCap => {}
+            // 无效的`zombie`在下一步中被当作可删除的`cap`
+            Err(cap) if cap.cap_type_or_null() == CapTag::CapZombieCap => {}
+            Err(_) => {
+                panic!("Expected recursion to result in Zombie.")
+            }
         }
-        exception_t::EXCEPTION_NONE
     }
 
     #[inline]
@@ -332,21 +438,30 @@ impl cte_t {
         }
     }
     // 撤销当前`cte`中的`capability`
+    #[cfg(feature = "extern_deps")]
     #[inline]
     pub fn revoke(&mut self) -> exception_t {
+        self.revoke_with(&mut ExternHooks, &mut KernelPreemptionPoint)
+    }
+
+    /// 与`revoke`相同，但通过`hooks`调用内核的其它部分，工作量记在`budget`上
+    pub fn revoke_with<H: CSpaceHooks, B: WorkBudget>(
+        &mut self,
+        hooks: &mut H,
+        budget: &mut B,
+    ) -> exception_t {
         while let Some(cte) = convert_to_option_mut_type_ref::<cte_t>(self.get_volatile_value()) {
             if !self.is_mdb_parent_of(cte) {
                 break;
             }
 
-            let mut status = cte.delete_all(true);
+            let status = cte.delete_all_with(true, hooks, budget);
             if status != exception_t::EXCEPTION_NONE {
                 return status;
             }
 
-            status = unsafe { preemptionPoint() };
-            if status != exception_t::EXCEPTION_NONE {
-                return status;
+            if budget.charge(1).is_err() {
+                return exception_t::EXCEPTION_PREEMTED;
             }
         }
         return exception_t::EXCEPTION_NONE;
@@ -354,6 +469,12 @@ impl cte_t {
 }
 
 
+/// The slot at a pointer returned by a lookup, the pointer must not be null.
+#[inline]
+pub(crate) fn convert_to_slot(slot: *mut cte_t) -> &'static mut cte_t {
+    convert_to_mut_type_ref::<cte_t>(slot as usize)
+}
+
 /// insert a new cap into dest_slot and set src_slot's next is dest_slot.
 pub fn cte_insert(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t) {
     let srcMDB = &mut src_slot.cteMDBNode;
@@ -365,12 +486,13 @@ pub fn cte_insert(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t)
     newMDB.set_first_badged(newCapIsRevocable as usize);
 
     /* Haskell error: "cteInsert to non-empty destination" */
-    assert_eq!(dest_slot.cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(dest_slot.cap.cap_type_or_null(), CapTag::CapNullCap);
     /* Haskell error: "cteInsert: mdb entry must be empty" */
     assert!(dest_slot.cteMDBNode.get_next() == 0 && dest_slot.cteMDBNode.get_prev() == 0);
 
     setUntypedCapAsFull(srcCap, new_cap, src_slot);
 
+    slot_cap_changed(dest_slot, &dest_slot.cap, new_cap);
     (*dest_slot).cap = new_cap.clone();
     (*dest_slot).cteMDBNode = newMDB;
     src_slot
@@ -382,12 +504,15 @@ pub fn cte_insert(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t)
             .cteMDBNode
             .set_prev(dest_slot as *const cte_t as usize);
     }
+    #[cfg(feature = "debug_mdb")]
+    assert_invariants(&[dest_slot as *const cte_t]);
 }
 
 
 /// insert a new cap to slot, set parent's next is slot.
 pub fn insert_new_cap(parent: &mut cte_t, slot: &mut cte_t, cap: &cap_t) {
     let next = parent.cteMDBNode.get_next();
+    slot_cap_changed(slot, &slot.cap, cap);
     slot.cap = cap.clone();
     slot.cteMDBNode = mdb_node_t::new(
         next as usize,
@@ -400,91 +525,124 @@ pub fn insert_new_cap(parent: &mut cte_t, slot: &mut cte_t, cap: &cap_t) {
         next_ref.cteMDBNode.set_prev(slot as *const cte_t as usize);
     }
     parent.cteMDBNode.set_next(slot as *const cte_t as usize);
+    #[cfg(feature = "debug_mdb")]
+    assert_invariants(&[slot as *const cte_t]);
 }
 
 /// move new cap into dest_slot and set src_slot's next is dest_slot
 pub fn cte_move(new_cap: &cap_t, src_slot: &mut cte_t, dest_slot: &mut cte_t) {
     /* Haskell error: "cteInsert to non-empty destination" */
-    assert_eq!(dest_slot.cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(dest_slot.cap.cap_type_or_null(), CapTag::CapNullCap);
     /* Haskell error: "cteInsert: mdb entry must be empty" */
     assert!(dest_slot.cteMDBNode.get_next() == 0 && dest_slot.cteMDBNode.get_prev() == 0);
     let mdb = src_slot.cteMDBNode;
+    slot_cap_changed(src_slot, &src_slot.cap, &cap_t::new_null_cap());
+    slot_cap_changed(dest_slot, &dest_slot.cap, new_cap);
     dest_slot.cap = new_cap.clone();
     src_slot.cap = cap_t::new_null_cap();
     dest_slot.cteMDBNode = mdb;
     src_slot.cteMDBNode = mdb_node_t::new(0, 0, 0, 0);
 
-    let prev_ptr = mdb.get_prev();
-    if prev_ptr != 0 {
-        let prev_ref = convert_to_mut_type_ref::<cte_t>(prev_ptr);
-        prev_ref
-            .cteMDBNode
-            .set_next(dest_slot as *const cte_t as usize);
-    }
-    let next_ptr = mdb.get_next();
-    if next_ptr != 0 {
-        let next_ref = convert_to_mut_type_ref::<cte_t>(next_ptr);
-        next_ref
-            .cteMDBNode
-            .set_prev(dest_slot as *const cte_t as usize);
-    }
+    mdb_relink(&mdb, dest_slot);
+    #[cfg(feature = "debug_mdb")]
+    assert_invariants(&[dest_slot as *const cte_t]);
 }
 
 /// swap two slots, set slot1.cap is cap2 , slot2.cap is cap1.
 pub fn cte_swap(cap1: &cap_t, slot1: &mut cte_t, cap2: &cap_t, slot2: &mut cte_t) {
     let mdb1 = slot1.cteMDBNode;
+    mdb_relink(&mdb1, slot2);
+    // 两个`slot`相邻时，上面已经修改了`slot2`的链接，所以要在之后读取
     let mdb2 = slot2.cteMDBNode;
-    {
-        let prev_ptr = mdb1.get_prev();
-        if prev_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(prev_ptr)
-                .cteMDBNode
-                .set_next(slot2 as *const cte_t as usize);
-        }
-        let next_ptr = mdb1.get_next();
-        if next_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(next_ptr)
-                .cteMDBNode
-                .set_prev(slot2 as *const cte_t as usize);
-        }
-    }
 
+    slot_cap_changed(slot1, &slot1.cap, cap2);
+    slot_cap_changed(slot2, &slot2.cap, cap1);
     slot1.cap = cap2.clone();
     //FIXME::result not right due to compiler
 
     slot2.cap = cap1.clone();
     slot1.cteMDBNode = mdb2;
     slot2.cteMDBNode = mdb1;
-    {
-        let prev_ptr = mdb2.get_prev();
-        if prev_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(prev_ptr)
-                .cteMDBNode
-                .set_next(slot1 as *const cte_t as usize);
-        }
-        let next_ptr = mdb2.get_next();
-        if next_ptr != 0 {
-            convert_to_mut_type_ref::<cte_t>(next_ptr)
-                .cteMDBNode
-                .set_prev(slot1 as *const cte_t as usize);
-        }
+    mdb_relink(&mdb2, slot1);
+    #[cfg(feature = "debug_mdb")]
+    assert_invariants(&[slot1 as *const cte_t, slot2 as *const cte_t]);
+}
+
+/// rotate three slots, move slot2 into slot3 with cap2 and slot1 into slot2 with cap1.
+///
+/// The three slots are distinct, rotating slot1 into itself is swapping slot1 and slot2 with
+/// `cte_swap`.
+pub fn cte_rotate(
+    cap1: &cap_t,
+    slot1: &mut cte_t,
+    cap2: &cap_t,
+    slot2: &mut cte_t,
+    slot3: &mut cte_t,
+) {
+    // The first move may relink `slot1`, so the second one must read its mdb afterwards.
+    cte_move(cap2, slot2, slot3);
+    cte_move(cap1, slot1, slot2);
+}
+
+/// Every change of the cap in a slot made by the cte operations goes through here, so that the
+/// indexes of the optional features stay up to date.
+#[inline]
+pub(crate) fn slot_cap_changed(slot: *const cte_t, old: &cap_t, new: &cap_t) {
+    #[cfg(feature = "region_index")]
+    crate::region_index::cap_changed(slot, old, new);
+    #[cfg(feature = "object_index")]
+    crate::object_index::cap_changed(slot, old, new);
+    #[cfg(not(any(feature = "region_index", feature = "object_index")))]
+    let _ = (slot, old, new);
+}
+
+/// Point the neighbours of `mdb` in the link list to `slot`, used when `slot` takes over `mdb`.
+#[inline]
+fn mdb_relink(mdb: &mdb_node_t, slot: *const cte_t) {
+    let prev_ptr = mdb.get_prev();
+    if prev_ptr != 0 {
+        convert_to_mut_type_ref::<cte_t>(prev_ptr)
+            .cteMDBNode
+            .set_next(slot as usize);
+    }
+    let next_ptr = mdb.get_next();
+    if next_ptr != 0 {
+        convert_to_mut_type_ref::<cte_t>(next_ptr)
+            .cteMDBNode
+            .set_prev(slot as usize);
     }
 }
 
 /// 判断当前`cap`能否被删除，只有`CNode Capability`能够做到`slot=z_slot`，且n==1意味着是`tcb`初始分配的`CNode`。
+///
+/// A zombie with an invalid type or number is removable like a null cap: the slots it has not
+/// deleted are left as they are, instead of reducing it out of the bounds of its object.
 #[inline]
 fn cap_removable(cap: &cap_t, slot: *mut cte_t) -> bool {
-    match cap.get_cap_type() {
-        CapTag::CapNullCap => {
-            return true;
+    zombie_to_reduce(cap, slot).is_none()
+}
+
+/// `finaliseCap`返回的`remainder`不可删除时，返回需要继续削减的`zombie`
+fn zombie_to_reduce(cap: &cap_t, slot: *mut cte_t) -> Option<ZombieCap> {
+    match ZombieCap::try_from(*cap) {
+        Ok(zombie) => {
+            let n = zombie.remaining_slots();
+            let z_slot = zombie.ptr() as *mut cte_t;
+            if n == 0 || (n == 1 && slot == z_slot) {
+                None
+            } else {
+                Some(zombie)
+            }
         }
-        CapTag::CapZombieCap => {
-            let n = cap.get_zombie_number();
-            let ptr = cap.get_zombie_ptr();
-            let z_slot = ptr as *mut cte_t;
-            return n == 0 || (n == 1 && slot == z_slot);
+        Err(cap)
+            if matches!(
+                cap.cap_type_or_null(),
+                CapTag::CapNullCap | CapTag::CapZombieCap
+            ) =>
+        {
+            None
         }
-        _ => {
+        Err(_) => {
             panic!("Invalid cap type , finaliseCap should only return Zombie or NullCap");
         }
     }
@@ -493,16 +651,16 @@ fn cap_removable(cap: &cap_t, slot: *mut cte_t) -> bool {
 /// 如果`srcCap`和`newCap`都是`UntypedCap`，并且指向同一块内存，内存大小也相同，就将`srcCap`记录为没有剩余空间。
 /// 自我认为是防止同一块内存空间被分配两次
 fn setUntypedCapAsFull(srcCap: &cap_t, newCap: &cap_t, srcSlot: &mut cte_t) {
-    if srcCap.get_cap_type() == CapTag::CapUntypedCap
-        && newCap.get_cap_type() == CapTag::CapUntypedCap
+    if let (Ok(srcUntyped), Ok(newUntyped)) =
+        (UntypedCap::try_from(*srcCap), UntypedCap::try_from(*newCap))
     {
-        assert_eq!(srcSlot.cap.get_cap_type(), CapTag::CapUntypedCap);
-        if srcCap.get_untyped_ptr() == newCap.get_untyped_ptr()
-            && srcCap.get_untyped_block_size() == newCap.get_untyped_block_size()
+        assert_eq!(srcSlot.cap.cap_type_or_null(), CapTag::CapUntypedCap);
+        if srcUntyped.ptr() == newUntyped.ptr()
+            && srcUntyped.block_size() == newUntyped.block_size()
         {
             srcSlot
                 .cap
-                .set_untyped_free_index(MAX_FREE_INDEX(srcCap.get_untyped_block_size()));
+                .set_untyped_free_index(MAX_FREE_INDEX(srcUntyped.block_size()));
         }
     }
 }
@@ -512,6 +670,11 @@ fn setUntypedCapAsFull(srcCap: &cap_t, newCap: &cap_t, srcSlot: &mut cte_t) {
 /// 从给定的cnode、cap index、和depth中找到对应cap的slot，成功则返回slot指针，失败返回找到的最深的cnode
 /// 
 /// Parse cap_ptr ,get a capbility from cnode.
+///
+/// On failure the returned `lookupFault` describes the failing level: `invalid_root` when `node_cap`
+/// is not a CNode cap, `guard_mismatch` when the guard does not match the cap_ptr, and
+/// `depth_mismatch` when the remaining bits can not cover a level. A `missing_capability` fault is
+/// raised by callers that require the resolved slot to be non-empty.
 #[allow(unreachable_code)]
 pub fn resolve_address_bits(
     node_cap: &cap_t,
@@ -523,8 +686,9 @@ pub fn resolve_address_bits(
     ret.bitsRemaining = n_bits;
     let mut nodeCap = node_cap.clone();
 
-    if unlikely(nodeCap.get_cap_type() != CapTag::CapCNodeCap) {
+    if unlikely(nodeCap.cap_type_or_null() != CapTag::CapCNodeCap) {
         ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
+        ret.lookupFault = lookup_fault_t::new_root_invalid();
         return ret;
     }
 
@@ -534,13 +698,16 @@ pub fn resolve_address_bits(
         let levelBits = radixBits + guardBits;
         assert_ne!(levelBits, 0);
         let capGuard = nodeCap.get_cnode_guard();
-        let guard = (cap_ptr >> ((n_bits - guardBits) & MASK!(wordRadix))) & MASK!(guardBits);
+        let guard =
+            (cap_ptr >> (n_bits.wrapping_sub(guardBits) & MASK!(wordRadix))) & MASK!(guardBits);
         if unlikely(guardBits > n_bits || guard != capGuard) {
             ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
+            ret.lookupFault = lookup_fault_t::new_guard_mismatch(capGuard, guardBits, n_bits);
             return ret;
         }
         if unlikely(levelBits > n_bits) {
             ret.status = exception_t::EXCEPTION_LOOKUP_FAULT;
+            ret.lookupFault = lookup_fault_t::new_depth_mismatch(levelBits, n_bits);
             return ret;
         }
         let offset = (cap_ptr >> (n_bits - levelBits)) & MASK!(radixBits);
@@ -553,7 +720,7 @@ pub fn resolve_address_bits(
         }
         n_bits -= levelBits;
         nodeCap = unsafe { (*slot).cap.clone() };
-        if unlikely(nodeCap.get_cap_type() != CapTag::CapCNodeCap) {
+        if unlikely(nodeCap.cap_type_or_null() != CapTag::CapCNodeCap) {
             ret.slot = slot;
             ret.bitsRemaining = n_bits;
             return ret;
@@ -1,9 +1,45 @@
 //! This module contains interfaces needed to be implemented by external module.
+//!
+//! The cspace operations reach the rest of the kernel only through `CSpaceHooks`, and are
+//! preempted through a `WorkBudget`. With the `extern_deps` feature (enabled by default),
+//! `ExternHooks` and `KernelPreemptionPoint` implement them with the symbols exported by the kernel.
 
 use crate::cap::cap_t;
 use crate::structures::finaliseCap_ret;
+use sel4_common::object::ObjectType;
+#[cfg(feature = "extern_deps")]
 use sel4_common::structures::exception_t;
+use sel4_common::BIT;
 
+/// Kernel services used by deletion, revocation, the CNode invocations and retype.
+pub trait CSpaceHooks {
+    /// Finalising a cap to make it being the end of link list.
+    fn finalise_cap(&mut self, cap: &cap_t, final_: bool, exposed: bool) -> finaliseCap_ret;
+
+    /// if the cap is CapIrqHandlerCap mask the interrupt number.
+    fn post_cap_deletion(&mut self, cap: &cap_t);
+
+    /// Cancel the messages with given badge sending to the endpoint.
+    fn cancel_badged_sends(&mut self, epptr: usize, badge: usize);
+
+    /// Initialise the memory of an object created by retype at `ptr`, before its cap is inserted.
+    /// Device memory must be left untouched.
+    fn init_object(
+        &mut self,
+        object_type: ObjectType,
+        ptr: usize,
+        user_size: usize,
+        device_memory: bool,
+    );
+
+    /// Clear `BIT!(size_bits)` bytes of memory at `ptr` when an untyped is reset.
+    #[inline]
+    fn clear_memory(&mut self, ptr: usize, size_bits: usize) {
+        unsafe { core::ptr::write_bytes(ptr as *mut u8, 0, BIT!(size_bits)) }
+    }
+}
+
+#[cfg(feature = "extern_deps")]
 extern "C" {
     /// Finalising an architectural cap to make it being the end of link list.
     pub fn Arch_finaliseCap(cap: &cap_t, final_: bool) -> finaliseCap_ret;
@@ -14,6 +50,46 @@ extern "C" {
     /// if the cap is CapIrqHandlerCap mask the interrupt number.
     pub fn post_cap_deletion(cap: &cap_t);
 
+    /// Cancel the messages with given badge sending to the endpoint.
+    pub fn cancelBadgedSends(epptr: usize, badge: usize);
+
+    /// Clear and set up a new object, `object_type` is the value of an `ObjectType`.
+    pub fn initObject(object_type: usize, ptr: usize, user_size: usize, device_memory: bool);
+
     /// Add 1 to ksWorkUnitsCompleted, and check whether ksWorkUnitsCompleted exceeds the limitation.
     pub fn preemptionPoint() -> exception_t;
 }
+
+/// `CSpaceHooks` implemented by the extern functions of the kernel.
+#[cfg(feature = "extern_deps")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExternHooks;
+
+#[cfg(feature = "extern_deps")]
+impl CSpaceHooks for ExternHooks {
+    #[inline]
+    fn finalise_cap(&mut self, cap: &cap_t, final_: bool, exposed: bool) -> finaliseCap_ret {
+        unsafe { finaliseCap(cap, final_, exposed) }
+    }
+
+    #[inline]
+    fn post_cap_deletion(&mut self, cap: &cap_t) {
+        unsafe { post_cap_deletion(cap) }
+    }
+
+    #[inline]
+    fn cancel_badged_sends(&mut self, epptr: usize, badge: usize) {
+        unsafe { cancelBadgedSends(epptr, badge) }
+    }
+
+    #[inline]
+    fn init_object(
+        &mut self,
+        object_type: ObjectType,
+        ptr: usize,
+        user_size: usize,
+        device_memory: bool,
+    ) {
+        unsafe { initObject(object_type as usize, ptr, user_size, device_memory) }
+    }
+}
@@ -0,0 +1,213 @@
+//! Traversals of the capability derivation tree, which is stored in the MDB list in pre-order:
+//! the descendants of a slot are the run of slots directly following it that it is the parent of
+//! (`cte_t::is_mdb_parent_of`), the same run `revoke` deletes.
+//!
+//! Badged endpoint and notification caps keep the semantics of `is_mdb_parent_of`: a badged cap
+//! is only the parent of the following copies with the same badge, up to the next `firstBadged`.
+//!
+//! The iterators must not be used while the list is being changed.
+
+use crate::cte::cte_t;
+use crate::object::ObjectKey;
+use sel4_common::utils::convert_to_type_ref;
+
+#[inline]
+fn slot_ref<'a>(ptr: usize) -> Option<&'a cte_t> {
+    if ptr == 0 {
+        None
+    } else {
+        Some(convert_to_type_ref::<cte_t>(ptr))
+    }
+}
+
+#[inline]
+fn next_slot<'a>(slot: &cte_t) -> Option<&'a cte_t> {
+    slot_ref(slot.cteMDBNode.get_next())
+}
+
+#[inline]
+fn prev_slot<'a>(slot: &cte_t) -> Option<&'a cte_t> {
+    slot_ref(slot.cteMDBNode.get_prev())
+}
+
+impl cte_t {
+    /// Iterate over this slot and all the slots after it in the MDB list.
+    pub fn mdb_iter(&self) -> MdbIter<'_> {
+        MdbIter { next: Some(self) }
+    }
+
+    /// Iterate over all the descendants of this slot in pre-order.
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants {
+            parent: self,
+            next: next_slot(self),
+        }
+    }
+
+    /// Iterate over the direct children of this slot.
+    pub fn children(&self) -> Children<'_> {
+        Children {
+            parent: self,
+            next: next_slot(self),
+        }
+    }
+
+    /// The slot this slot was derived from, `None` for an original cap.
+    ///
+    /// It is the nearest previous slot which is the parent of this one, which relies on the
+    /// descendants being contiguous (see `check_invariants`).
+    pub fn parent(&self) -> Option<&cte_t> {
+        let mut slot = prev_slot(self);
+        while let Some(cte) = slot {
+            if cte.is_mdb_parent_of(self) {
+                return Some(cte);
+            }
+            slot = prev_slot(cte);
+        }
+        None
+    }
+
+    /// Iterate over the parent of this slot, the parent of that, and so on up to the original cap.
+    pub fn ancestors(&self) -> Ancestors<'_> {
+        Ancestors {
+            next: self.parent(),
+        }
+    }
+
+    /// Iterate over the other children of this slot's parent, empty for an original cap.
+    pub fn siblings(&self) -> Siblings<'_> {
+        Siblings {
+            slot: self,
+            children: self.parent().map(|parent| parent.children()),
+        }
+    }
+
+    /// Iterate over the slots holding caps to the same object as this slot (see `ObjectKey`), in
+    /// the run of the MDB list around it, from its first slot. Empty if the cap has no object.
+    ///
+    /// Copies and derived caps, badged ones included, are inserted after the cap they come from,
+    /// so the caps to an object derived from one original cap are one run. Reply caps are not in
+    /// the run of the thread caps of their TCB, and the copies of an untyped can be separated by
+    /// its children; the `object_index` feature finds those too.
+    pub fn object_slots(&self) -> ObjectSlots<'_> {
+        let key = ObjectKey::of_cap(&self.cap);
+        let mut first = key.map(|_| self);
+        while let Some(prev) = first
+            .and_then(prev_slot)
+            .filter(|slot| ObjectKey::of_cap(&slot.cap) == key)
+        {
+            first = Some(prev);
+        }
+        ObjectSlots { key, next: first }
+    }
+}
+
+/// Iterator returned by `cte_t::mdb_iter`.
+#[derive(Clone)]
+pub struct MdbIter<'a> {
+    next: Option<&'a cte_t>,
+}
+
+impl<'a> Iterator for MdbIter<'a> {
+    type Item = &'a cte_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.next?;
+        self.next = next_slot(slot);
+        Some(slot)
+    }
+}
+
+/// Iterator returned by `cte_t::descendants`.
+#[derive(Clone)]
+pub struct Descendants<'a> {
+    parent: &'a cte_t,
+    next: Option<&'a cte_t>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a cte_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self
+            .next
+            .filter(|slot| self.parent.is_mdb_parent_of(slot))?;
+        self.next = next_slot(slot);
+        Some(slot)
+    }
+}
+
+/// Iterator returned by `cte_t::children`.
+#[derive(Clone)]
+pub struct Children<'a> {
+    parent: &'a cte_t,
+    next: Option<&'a cte_t>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = &'a cte_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let child = self
+            .next
+            .filter(|slot| self.parent.is_mdb_parent_of(slot))?;
+        // 跳过`child`自己的子孙，下一个不是它子孙的`slot`才可能是下一个子节点
+        let mut slot = next_slot(child);
+        while let Some(cte) = slot.filter(|slot| child.is_mdb_parent_of(slot)) {
+            slot = next_slot(cte);
+        }
+        self.next = slot;
+        Some(child)
+    }
+}
+
+/// Iterator returned by `cte_t::ancestors`.
+#[derive(Clone)]
+pub struct Ancestors<'a> {
+    next: Option<&'a cte_t>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a cte_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.next?;
+        self.next = slot.parent();
+        Some(slot)
+    }
+}
+
+/// Iterator returned by `cte_t::siblings`.
+#[derive(Clone)]
+pub struct Siblings<'a> {
+    slot: &'a cte_t,
+    children: Option<Children<'a>>,
+}
+
+impl<'a> Iterator for Siblings<'a> {
+    type Item = &'a cte_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.slot;
+        self.children
+            .as_mut()?
+            .find(|child| !core::ptr::eq(*child, slot))
+    }
+}
+
+/// Iterator returned by `cte_t::object_slots`.
+#[derive(Clone)]
+pub struct ObjectSlots<'a> {
+    key: Option<ObjectKey>,
+    next: Option<&'a cte_t>,
+}
+
+impl<'a> Iterator for ObjectSlots<'a> {
+    type Item = &'a cte_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.next?;
+        self.next = next_slot(slot).filter(|slot| ObjectKey::of_cap(&slot.cap) == self.key);
+        Some(slot)
+    }
+}
@@ -0,0 +1,209 @@
+//! Print the capability derivation tree through any `core::fmt::Write`, as an indented text tree or
+//! as a Graphviz DOT graph, e.g. to the kernel console when a revoke deletes more than expected.
+//!
+//! Each node shows the slot address, the cap type, the object pointer, the badge of endpoint and
+//! notification caps, the kind and remaining slots of zombies, and the revocable (`R`) and
+//! firstBadged (`F`) bits.
+
+use crate::cap::view::ZombieCap;
+use crate::cap::zombie::ZombieKind;
+use crate::cap::{cap_t, CapTag};
+use crate::cte::cte_t;
+use crate::walk::CSpaceWalker;
+use core::fmt::{self, Write};
+use sel4_common::utils::{convert_to_mut_type_ref, convert_to_type_ref};
+
+/// Descendants this deep or deeper are left out, their number is written after the tree.
+pub const MAX_TREE_DEPTH: usize = 64;
+
+/// Bit 63 of the second MDB word, above `mdbNext`, is not used by the kernel. It marks the roots
+/// already written by `for_each_cnode_root`, which clears it before returning.
+const ROOT_MARK: usize = 1 << 63;
+
+/// The label of a slot in both outputs.
+struct SlotLabel<'a>(&'a cte_t);
+
+impl fmt::Display for SlotLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cte = self.0;
+        let cap = &cte.cap;
+        write!(f, "{:#x} ", cte.get_ptr())?;
+        let tag = match cap.try_cap_type() {
+            Ok(tag) => tag,
+            Err(raw) => return write!(f, "<invalid cap type {}>", raw),
+        };
+        if tag == CapTag::CapZombieCap && ZombieKind::from_word(cap.get_zombie_type()).is_none() {
+            return write!(f, "<invalid zombie type {}>", cap.get_zombie_type());
+        }
+        write!(f, "{:?} ptr={:#x}", tag, cap.get_cap_ptr())?;
+        if let Some(badge) = badge_of(cap, tag) {
+            write!(f, " badge={:#x}", badge)?;
+        }
+        if let Ok(zombie) = ZombieCap::try_from(*cap) {
+            write!(
+                f,
+                " {:?} remaining={}",
+                zombie.kind(),
+                zombie.remaining_slots()
+            )?;
+        }
+        let mdb = &cte.cteMDBNode;
+        let revocable = if mdb.get_revocable() != 0 { 'R' } else { '-' };
+        let first_badged = if mdb.get_first_badged() != 0 {
+            'F'
+        } else {
+            '-'
+        };
+        write!(f, " [{}{}]", revocable, first_badged)
+    }
+}
+
+#[inline]
+fn badge_of(cap: &cap_t, tag: CapTag) -> Option<usize> {
+    match tag {
+        CapTag::CapEndpointCap => Some(cap.get_ep_badge()),
+        CapTag::CapNotificationCap => Some(cap.get_nf_badge()),
+        _ => None,
+    }
+}
+
+/// 前序遍历`root`及其子孙，`visit(depth, parent, slot)`，返回深度达到`MAX_TREE_DEPTH`而未访问的
+/// `slot`数量
+fn walk_tree<F>(root: &cte_t, mut visit: F) -> Result<usize, fmt::Error>
+where
+    F: FnMut(usize, Option<&cte_t>, &cte_t) -> fmt::Result,
+{
+    let mut stack: [&cte_t; MAX_TREE_DEPTH] = [root; MAX_TREE_DEPTH];
+    let mut depth = 1;
+    let mut truncated = 0;
+    visit(0, None, root)?;
+    for slot in root.descendants() {
+        // `is_mdb_parent_of`对所有子孙成立，栈中最深的祖先才是`parent`
+        while depth > 1 && !stack[depth - 1].is_mdb_parent_of(slot) {
+            depth -= 1;
+        }
+        if depth == MAX_TREE_DEPTH {
+            truncated += 1;
+            continue;
+        }
+        visit(depth, Some(stack[depth - 1]), slot)?;
+        stack[depth] = slot;
+        depth += 1;
+    }
+    Ok(truncated)
+}
+
+/// 沿`prev`向前找到`slot`所在树的根，与`ancestors().last()`相同，但只遍历一次链表
+fn root_of(slot: &cte_t) -> &cte_t {
+    let mut root = slot;
+    let mut prev = slot.cteMDBNode.get_prev();
+    while prev != 0 {
+        let cte = convert_to_type_ref::<cte_t>(prev);
+        if cte.is_mdb_parent_of(root) {
+            root = cte;
+        }
+        prev = cte.cteMDBNode.get_prev();
+    }
+    root
+}
+
+#[inline]
+fn set_root_mark(root: &cte_t, marked: bool) {
+    let mdb = &mut convert_to_mut_type_ref::<cte_t>(root.get_ptr()).cteMDBNode;
+    if marked {
+        mdb.words[1] |= ROOT_MARK;
+    } else {
+        mdb.words[1] &= !ROOT_MARK;
+    }
+}
+
+/// The original caps of the trees having a slot reachable from the CNode (see `CSpaceWalker`),
+/// each given once. `fmt::Error` if `cnode` is not a CNode cap.
+///
+/// The roots are marked in the MDB while walking, so the slots must not be changed meanwhile.
+fn for_each_cnode_root<F>(cnode: &cap_t, mut f: F) -> fmt::Result
+where
+    F: FnMut(&cte_t) -> fmt::Result,
+{
+    if cnode.cap_type_or_null() != CapTag::CapCNodeCap {
+        return Err(fmt::Error);
+    }
+    let mut ret = Ok(());
+    for (_, _, slot) in CSpaceWalker::new(cnode) {
+        let root = root_of(slot);
+        if root.cteMDBNode.words[1] & ROOT_MARK == 0 {
+            set_root_mark(root, true);
+            ret = f(root);
+            if ret.is_err() {
+                break;
+            }
+        }
+    }
+    // 出错时也要清除全部标记
+    for (_, _, slot) in CSpaceWalker::new(cnode) {
+        set_root_mark(root_of(slot), false);
+    }
+    ret
+}
+
+/// Write the derivation tree rooted at `root` as a text tree, indented by two spaces per level.
+pub fn write_mdb_tree<W: Write>(out: &mut W, root: &cte_t) -> fmt::Result {
+    let truncated = walk_tree(root, |depth, _, slot| {
+        for _ in 0..depth {
+            out.write_str("  ")?;
+        }
+        writeln!(out, "{}", SlotLabel(slot))
+    })?;
+    if truncated != 0 {
+        writeln!(
+            out,
+            "... {} slots at depth {} or deeper not shown",
+            truncated, MAX_TREE_DEPTH
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the derivation trees of all the caps reachable from the CNode as text trees.
+pub fn write_cnode_mdb_tree<W: Write>(out: &mut W, cnode: &cap_t) -> fmt::Result {
+    for_each_cnode_root(cnode, |root| write_mdb_tree(out, root))
+}
+
+fn write_dot_nodes<W: Write>(out: &mut W, root: &cte_t) -> fmt::Result {
+    let truncated = walk_tree(root, |_, parent, slot| {
+        writeln!(
+            out,
+            "    n{:x} [label=\"{}\"];",
+            slot.get_ptr(),
+            SlotLabel(slot)
+        )?;
+        match parent {
+            Some(parent) => writeln!(out, "    n{:x} -> n{:x};", parent.get_ptr(), slot.get_ptr()),
+            None => Ok(()),
+        }
+    })?;
+    if truncated != 0 {
+        writeln!(
+            out,
+            "    // {} slots under n{:x} at depth {} or deeper not shown",
+            truncated,
+            root.get_ptr(),
+            MAX_TREE_DEPTH
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the derivation tree rooted at `root` as a Graphviz DOT digraph.
+pub fn write_mdb_dot<W: Write>(out: &mut W, root: &cte_t) -> fmt::Result {
+    out.write_str("digraph mdb {\n    node [shape=box];\n")?;
+    write_dot_nodes(out, root)?;
+    out.write_str("}\n")
+}
+
+/// Write the derivation trees of all the caps reachable from the CNode as one Graphviz DOT digraph.
+pub fn write_cnode_mdb_dot<W: Write>(out: &mut W, cnode: &cap_t) -> fmt::Result {
+    out.write_str("digraph mdb {\n    node [shape=box];\n")?;
+    for_each_cnode_root(cnode, |root| write_dot_nodes(out, root))?;
+    out.write_str("}\n")
+}
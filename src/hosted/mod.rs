@@ -0,0 +1,312 @@
+//! Hosted mode, runs the cspace in an ordinary process instead of a booted kernel.
+//!
+//! `Arena` hands out zeroed, naturally aligned kernel objects from an `mmap`ed region, and
+//! `HostedKernel` implements `CSpaceHooks` with `finalise_cap`, a reference implementation of
+//! seL4 `finaliseCap` which turns the final CNode and TCB caps into zombies.
+//!
+//! ```ignore
+//! let mut arena = Arena::new(20);
+//! let mut kernel = HostedKernel::default();
+//! let root = arena.new_cnode(4, 0, 0);
+//! let ep = arena.new_endpoint();
+//! let src = cnode_slot(&root, 0);
+//! insert_new_cap(src, cnode_slot(&root, 1), &ep);
+//! src.delete_all_with(true, &mut kernel, &mut Unbounded);
+//! ```
+//!
+//! `restart` checks that preempted deletions and revocations can be restarted.
+
+pub mod restart;
+
+use crate::cap::view::ZombieCap;
+use crate::cap::zombie::ZombieKind;
+use crate::cap::{cap_t, CapTag};
+use crate::cte::{cte_t, slot_cap_changed};
+use crate::deps::CSpaceHooks;
+use crate::object::{cap_for_new_object, object_size_bits};
+use crate::structures::finaliseCap_ret;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use sel4_common::object::ObjectType;
+use sel4_common::sel4_config::{seL4_SlotBits, seL4_TCBBits, tcbCNodeEntries};
+use sel4_common::utils::convert_to_mut_type_ref;
+use sel4_common::{BIT, MASK, ROUND_UP};
+use std::vec::Vec;
+
+/// Pointers in caps only keep 39 bits, so the arenas are mapped below this address.
+const ARENA_ADDR_LIMIT: usize = BIT!(38);
+/// The first address tried for an arena, each arena gets its own `ARENA_STRIDE` window.
+const ARENA_HINT_BASE: usize = BIT!(36);
+const ARENA_STRIDE: usize = BIT!(32);
+/// The largest arena, so that an arena always fits in its window.
+pub const ARENA_MAX_BITS: usize = 32;
+
+static NEXT_ARENA_HINT: AtomicUsize = AtomicUsize::new(ARENA_HINT_BASE);
+
+/// A bump allocator for kernel objects, backed by anonymous memory mapped at a low address.
+///
+/// Objects are never freed, the whole region is unmapped when the arena is dropped, so caps and
+/// slots obtained from an arena must not be used after that.
+pub struct Arena {
+    base: usize,
+    size: usize,
+    next: usize,
+}
+
+impl Arena {
+    /// Map an arena of `BIT!(size_bits)` bytes.
+    pub fn new(size_bits: usize) -> Self {
+        assert!(size_bits <= ARENA_MAX_BITS, "arena too large");
+        let size = BIT!(size_bits);
+        loop {
+            let hint = NEXT_ARENA_HINT.fetch_add(ARENA_STRIDE, Ordering::Relaxed);
+            assert!(
+                hint + ARENA_STRIDE <= ARENA_ADDR_LIMIT,
+                "out of arena address space"
+            );
+            let addr = unsafe {
+                libc::mmap(
+                    hint as *mut libc::c_void,
+                    size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                    -1,
+                    0,
+                )
+            };
+            assert!(addr != libc::MAP_FAILED, "mmap failed");
+            let base = addr as usize;
+            if base + size <= ARENA_ADDR_LIMIT {
+                return Arena {
+                    base,
+                    size,
+                    next: base,
+                };
+            }
+            // 提示地址已被占用，内核给出的地址无法放入`cap`中，换下一个窗口重试
+            unsafe { libc::munmap(addr, size) };
+        }
+    }
+
+    /// Allocate a zeroed object of `BIT!(size_bits)` bytes aligned to its size.
+    pub fn alloc(&mut self, size_bits: usize) -> usize {
+        let ptr = ROUND_UP!(self.next, size_bits);
+        assert!(
+            ptr + BIT!(size_bits) <= self.base + self.size,
+            "arena exhausted"
+        );
+        self.next = ptr + BIT!(size_bits);
+        ptr
+    }
+
+    /// Allocate an object and return the cap created by retype for it.
+    pub fn new_object(&mut self, object_type: ObjectType, user_size: usize) -> cap_t {
+        let ptr = self.alloc(object_size_bits(object_type, user_size));
+        cap_for_new_object(object_type, ptr, user_size, false)
+    }
+
+    /// Allocate a CNode with `BIT!(radix)` empty slots and return a cap to it.
+    pub fn new_cnode(&mut self, radix: usize, guard_size: usize, guard: usize) -> cap_t {
+        let mut cap = self.new_object(ObjectType::CapTableObject, radix);
+        cap.set_cnode_guard_size(guard_size);
+        cap.set_cnode_guard(guard);
+        cap
+    }
+
+    /// Allocate a TCB and return a cap to it, its CNode slots are reached with `tcb_slot`.
+    pub fn new_tcb(&mut self) -> cap_t {
+        self.new_object(ObjectType::TCBObject, 0)
+    }
+
+    /// Allocate an endpoint and return a cap with all rights.
+    pub fn new_endpoint(&mut self) -> cap_t {
+        self.new_object(ObjectType::EndpointObject, 0)
+    }
+
+    /// Allocate a notification and return a cap with all rights.
+    pub fn new_notification(&mut self) -> cap_t {
+        self.new_object(ObjectType::NotificationObject, 0)
+    }
+
+    /// Allocate an untyped region of `BIT!(size_bits)` bytes and return a cap to it.
+    pub fn new_untyped(&mut self, size_bits: usize) -> cap_t {
+        self.new_object(ObjectType::UnytpedObject, size_bits)
+    }
+
+    /// Copy of the memory allocated so far, with the slots of the arena in the indexes of the
+    /// optional features.
+    pub fn snapshot(&self) -> Snapshot {
+        let used = self.next - self.base;
+        let memory = unsafe { core::slice::from_raw_parts(self.base as *const u8, used) };
+        Snapshot {
+            memory: memory.to_vec(),
+            indexed: indexed_slots(self.base, self.next),
+        }
+    }
+
+    /// Put back the memory saved by `snapshot`, objects allocated since then are cleared.
+    ///
+    /// The index entries of the arena are replaced by those of the slots indexed when the
+    /// snapshot was taken, read from the restored memory.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let used = self.next - self.base;
+        let memory = &snapshot.memory;
+        assert!(memory.len() <= used);
+        unsafe {
+            let base = self.base as *mut u8;
+            core::ptr::copy_nonoverlapping(memory.as_ptr(), base, memory.len());
+            core::ptr::write_bytes(base.add(memory.len()), 0, used - memory.len());
+        }
+        self.next = self.base + memory.len();
+        forget_slots(self.base, self.base + self.size);
+        reindex_slots(&snapshot.indexed);
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // 内存解除映射后，索引中不能留下指向其中`slot`的条目
+        forget_slots(self.base, self.base + self.size);
+        unsafe { libc::munmap(self.base as *mut libc::c_void, self.size) };
+    }
+}
+
+/// The memory of an `Arena` saved by `Arena::snapshot`.
+pub struct Snapshot {
+    memory: Vec<u8>,
+    /// The slots with an entry in one of the indexes.
+    indexed: Vec<usize>,
+}
+
+/// The slots in `[start, end)` with an entry in one of the indexes.
+fn indexed_slots(start: usize, end: usize) -> Vec<usize> {
+    let mut slots = Vec::new();
+    #[cfg(feature = "region_index")]
+    slots.extend(crate::region_index::slots_in(start, end));
+    #[cfg(feature = "object_index")]
+    slots.extend(crate::object_index::slots_in(start, end));
+    #[cfg(not(any(feature = "region_index", feature = "object_index")))]
+    let _ = (start, end);
+    slots.sort();
+    slots.dedup();
+    slots
+}
+
+fn forget_slots(start: usize, end: usize) {
+    #[cfg(feature = "region_index")]
+    crate::region_index::forget_slots(start, end);
+    #[cfg(feature = "object_index")]
+    crate::object_index::forget_slots(start, end);
+    #[cfg(not(any(feature = "region_index", feature = "object_index")))]
+    let _ = (start, end);
+}
+
+/// Index the caps the slots hold now.
+fn reindex_slots(slots: &[usize]) {
+    for &slot in slots {
+        let cap = convert_to_mut_type_ref::<cte_t>(slot).cap;
+        slot_cap_changed(slot as *const cte_t, &cap_t::new_null_cap(), &cap);
+    }
+}
+
+/// The `index`th slot of the CNode the cap points to.
+pub fn cnode_slot(cnode: &cap_t, index: usize) -> &'static mut cte_t {
+    assert_eq!(cnode.get_cap_type(), CapTag::CapCNodeCap);
+    assert!(index < BIT!(cnode.get_cnode_radix()));
+    convert_to_mut_type_ref::<cte_t>(cnode.get_cnode_ptr() + (index << seL4_SlotBits))
+}
+
+/// The `index`th CNode slot of the TCB the cap points to, `index` is one of `tcbCTable`...
+pub fn tcb_slot(tcb: &cap_t, index: usize) -> &'static mut cte_t {
+    assert_eq!(tcb.get_cap_type(), CapTag::CapThreadCap);
+    assert!(index < tcbCNodeEntries);
+    let base = tcb.get_tcb_ptr() & !MASK!(seL4_TCBBits);
+    convert_to_mut_type_ref::<cte_t>(base + (index << seL4_SlotBits))
+}
+
+/// Reference implementation of seL4 `finaliseCap` for the hosted mode.
+///
+/// Objects are not torn down (there are no threads or queues to clean), but the remainder and the
+/// cleanup info are the ones of the real kernel: the final CNode and TCB caps become zombies whose
+/// slots are deleted by `delete_all`, and the final IRQ handler cap is handed to `post_cap_deletion`.
+pub fn finalise_cap(cap: &cap_t, final_: bool, exposed: bool) -> finaliseCap_ret {
+    let mut ret = finaliseCap_ret::default();
+    if cap.isArchCap() {
+        return ret;
+    }
+    match cap.cap_type_or_null() {
+        CapTag::CapEndpointCap
+        | CapTag::CapNotificationCap
+        | CapTag::CapReplyCap
+        | CapTag::CapNullCap
+        | CapTag::CapDomainCap => return ret,
+        _ => {}
+    }
+    if exposed {
+        panic!("finaliseCap: failed to finalise immediately.");
+    }
+    match cap.cap_type_or_null() {
+        CapTag::CapCNodeCap if final_ => {
+            let radix = cap.get_cnode_radix();
+            let kind = ZombieKind::CNode { radix };
+            ret.remainder = zombie(kind, cap.get_cnode_ptr());
+        }
+        CapTag::CapThreadCap if final_ => {
+            let cte_ptr = cap.get_tcb_ptr() & !MASK!(seL4_TCBBits);
+            ret.remainder = zombie(ZombieKind::Tcb, cte_ptr);
+        }
+        CapTag::CapZombieCap => {
+            ret.remainder = *cap;
+        }
+        CapTag::CapIrqHandlerCap if final_ => {
+            ret.cleanupInfo = *cap;
+        }
+        _ => {}
+    }
+    ret
+}
+
+/// 所有`slot`都未删除的`zombie cap`
+fn zombie(kind: ZombieKind, ptr: usize) -> cap_t {
+    ZombieCap::new(kind, ptr, kind.slots()).unwrap().into()
+}
+
+/// `CSpaceHooks` of the hosted mode, records what the rest of the kernel would have been asked to do.
+#[derive(Debug, Default)]
+pub struct HostedKernel {
+    /// The cleanup info passed to `post_cap_deletion`, except null caps.
+    pub deleted: Vec<cap_t>,
+    /// `(epptr, badge)` of each `cancel_badged_sends`.
+    pub cancelled_badges: Vec<(usize, usize)>,
+    /// `(object_type, ptr)` of each object created by retype.
+    pub created: Vec<(ObjectType, usize)>,
+}
+
+impl CSpaceHooks for HostedKernel {
+    fn finalise_cap(&mut self, cap: &cap_t, final_: bool, exposed: bool) -> finaliseCap_ret {
+        finalise_cap(cap, final_, exposed)
+    }
+
+    fn post_cap_deletion(&mut self, cap: &cap_t) {
+        if cap.cap_type_or_null() != CapTag::CapNullCap {
+            self.deleted.push(*cap);
+        }
+    }
+
+    fn cancel_badged_sends(&mut self, epptr: usize, badge: usize) {
+        self.cancelled_badges.push((epptr, badge));
+    }
+
+    fn init_object(
+        &mut self,
+        object_type: ObjectType,
+        ptr: usize,
+        user_size: usize,
+        device_memory: bool,
+    ) {
+        if !device_memory {
+            let size = BIT!(object_size_bits(object_type, user_size));
+            unsafe { core::ptr::write_bytes(ptr as *mut u8, 0, size) };
+        }
+        self.created.push((object_type, ptr));
+    }
+}
@@ -0,0 +1,223 @@
+//! Deterministic preemption, to check that preempted deletions and revocations can be restarted.
+//!
+//! `check_restartability` runs an operation once without preemption, then again from the same
+//! initial state for every work unit N, preempted at the Nth unit only and at every Nth unit,
+//! restarting it from scratch like the kernel restarts a preempted syscall. Every run must end
+//! with the same status and the same CSpace as the run without preemption, with no zombie left
+//! and the MDB invariants holding.
+
+use super::{Arena, HostedKernel};
+use crate::budget::{Preempted, WorkBudget};
+use crate::cap::{cap_t, CapTag};
+use crate::cte::cte_t;
+use crate::mdb::{check_invariants, MdbViolation};
+use crate::walk::CSpaceWalker;
+use sel4_common::structures::exception_t;
+use sel4_common::utils::convert_to_mut_type_ref;
+use std::vec::Vec;
+
+/// A `WorkBudget` preempting at the Nth unit charged, either once or at every Nth unit.
+#[derive(Clone, Copy, Debug)]
+pub struct PreemptAt {
+    at: usize,
+    repeat: bool,
+    charged: usize,
+}
+
+impl PreemptAt {
+    /// Preempt when the `n`th unit is charged, and never again.
+    pub fn once(n: usize) -> Self {
+        PreemptAt {
+            at: n,
+            repeat: false,
+            charged: 0,
+        }
+    }
+
+    /// Preempt when every `n`th unit is charged.
+    pub fn every(n: usize) -> Self {
+        assert!(n > 0);
+        PreemptAt {
+            at: n,
+            repeat: true,
+            charged: 0,
+        }
+    }
+
+    /// Never preempt, only count the units.
+    pub fn never() -> Self {
+        Self::once(0)
+    }
+
+    /// The number of units charged so far.
+    #[inline]
+    pub fn charged(&self) -> usize {
+        self.charged
+    }
+}
+
+impl WorkBudget for PreemptAt {
+    fn charge(&mut self, units: usize) -> Result<(), Preempted> {
+        let before = self.charged;
+        self.charged += units;
+        // 判断(before, charged]中是否有需要抢占的单位
+        let fire = match (self.at, self.repeat) {
+            (0, _) => false,
+            (at, false) => before < at && at <= self.charged,
+            (at, true) => before / at != self.charged / at,
+        };
+        if fire {
+            Err(Preempted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// One preempted run of `check_restartability`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trial {
+    /// The work unit the operation is preempted at.
+    pub n: usize,
+    /// Whether it is preempted at every `n`th unit instead of only the `n`th one.
+    pub every: bool,
+}
+
+/// Why a run did not end like the run without preemption.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartFailure {
+    /// The operation was still preempted after `restarts` restarts.
+    NoProgress { trial: Trial, restarts: usize },
+    /// The operation ended with `status` instead of `expected`.
+    Status {
+        trial: Trial,
+        expected: exception_t,
+        status: exception_t,
+    },
+    /// The CSpace differs from the one of the run without preemption, `trial` is `None` for that
+    /// run itself.
+    State { trial: Option<Trial> },
+    /// A zombie cap is left at `(cptr, depth)`.
+    Zombie {
+        trial: Option<Trial>,
+        cptr: usize,
+        depth: usize,
+    },
+    /// The MDB invariants are broken.
+    Mdb {
+        trial: Option<Trial>,
+        violation: MdbViolation,
+    },
+}
+
+/// Summary of a successful `check_restartability`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestartReport {
+    /// The status of the operation.
+    pub status: exception_t,
+    /// The units charged by the run without preemption, the number of N checked.
+    pub work_units: usize,
+    /// The number of preempted runs.
+    pub trials: usize,
+}
+
+/// Run `op` on the slot returned by `setup` once without preemption and once for each trial, see
+/// the module documentation.
+///
+/// `setup` builds the initial CSpace in the arena and returns its root CNode cap and the slot to
+/// operate on. `op` is called again after each preemption, e.g.
+/// `|slot, kernel, budget| slot.delete_all_with(true, kernel, budget)`.
+pub fn check_restartability<S, O>(
+    arena_bits: usize,
+    setup: S,
+    mut op: O,
+) -> Result<RestartReport, RestartFailure>
+where
+    S: FnOnce(&mut Arena) -> (cap_t, *mut cte_t),
+    O: FnMut(&mut cte_t, &mut HostedKernel, &mut PreemptAt) -> exception_t,
+{
+    let mut arena = Arena::new(arena_bits);
+    let (root, slot) = setup(&mut arena);
+    let initial = arena.snapshot();
+
+    let mut kernel = HostedKernel::default();
+    let mut budget = PreemptAt::never();
+    let expected = op(
+        convert_to_mut_type_ref::<cte_t>(slot as usize),
+        &mut kernel,
+        &mut budget,
+    );
+    let work_units = budget.charged();
+    check_final_state(&root, None)?;
+    let expected_state = cspace_state(&root);
+
+    let mut trials = 0;
+    for n in 1..=work_units {
+        for every in [false, true] {
+            let trial = Trial { n, every };
+            arena.restore(&initial);
+            let mut kernel = HostedKernel::default();
+            let mut budget = if every {
+                PreemptAt::every(n)
+            } else {
+                PreemptAt::once(n)
+            };
+            // 每次重启至少完成一个单位的工作，超过总工作量仍被抢占说明没有进展
+            let max_restarts = work_units + 1;
+            let mut restarts = 0;
+            let status = loop {
+                let slot = convert_to_mut_type_ref::<cte_t>(slot as usize);
+                let status = op(slot, &mut kernel, &mut budget);
+                if status != exception_t::EXCEPTION_PREEMTED {
+                    break status;
+                }
+                restarts += 1;
+                if restarts > max_restarts {
+                    return Err(RestartFailure::NoProgress { trial, restarts });
+                }
+            };
+            if status != expected {
+                return Err(RestartFailure::Status {
+                    trial,
+                    expected,
+                    status,
+                });
+            }
+            check_final_state(&root, Some(trial))?;
+            if cspace_state(&root) != expected_state {
+                return Err(RestartFailure::State { trial: Some(trial) });
+            }
+            trials += 1;
+        }
+    }
+    Ok(RestartReport {
+        status: expected,
+        work_units,
+        trials,
+    })
+}
+
+/// `(cptr, depth, cap, mdb)` of every slot reachable from `root`.
+fn cspace_state(root: &cap_t) -> Vec<(usize, usize, [usize; 2], [usize; 2])> {
+    CSpaceWalker::new(root)
+        .map(|(cptr, depth, slot)| (cptr, depth, slot.cap.words, slot.cteMDBNode.words))
+        .collect()
+}
+
+fn check_final_state(root: &cap_t, trial: Option<Trial>) -> Result<(), RestartFailure> {
+    let mut slots = Vec::new();
+    for (cptr, depth, slot) in CSpaceWalker::new(root) {
+        if slot.cap.cap_type_or_null() == CapTag::CapZombieCap {
+            return Err(RestartFailure::Zombie { trial, cptr, depth });
+        }
+        slots.push(slot as *const cte_t);
+    }
+    let mut first = None;
+    check_invariants(&slots, |violation| {
+        first.get_or_insert(violation);
+    });
+    match first {
+        Some(violation) => Err(RestartFailure::Mdb { trial, violation }),
+        None => Ok(()),
+    }
+}
@@ -1,9 +1,46 @@
 //! This module used contains interfaces provided to external modules.
 
+pub use super::cap::view::{
+    ASIDPoolCap, CNodeCap, EndpointCap, FrameCap, IrqHandlerCap, NotificationCap, PageTableCap,
+    ReplyCap, ThreadCap, UntypedCap, ZombieCap,
+};
+pub use super::budget::{FixedBudget, Preempted, Unbounded, WorkBudget};
+#[cfg(feature = "extern_deps")]
+pub use super::budget::KernelPreemptionPoint;
+pub use super::cap::zombie::{ZombieError, ZombieKind};
 pub use super::cap::CapTag;
-pub use super::cap::{cap_t, same_object_as};
-pub use super::cap_rights::seL4_CapRights_t;
-pub use super::mdb::mdb_node_t;
+pub use super::cap::region::ObjectRegion;
+pub use super::cap::{cap_t, same_object_as, same_region_as};
+pub use super::cap_rights::{seL4_CapRights_t, vm_rights_t};
+pub use super::cnode_invocation::{decode_cnode_invocation, invoke_cnode, CNodeInvocation};
+pub use super::continuation::{CSpaceContinuation, CSpaceOperation};
+pub use super::deps::CSpaceHooks;
+pub use super::dump::{
+    write_cnode_mdb_dot, write_cnode_mdb_tree, write_mdb_dot, write_mdb_tree, MAX_TREE_DEPTH,
+};
+pub use super::derivation::{Ancestors, Children, Descendants, MdbIter, ObjectSlots, Siblings};
+#[cfg(feature = "extern_deps")]
+pub use super::deps::ExternHooks;
+pub use super::mdb::{assert_invariants, check_invariants, mdb_node_t, MdbViolation};
 
-pub use super::cte::{cte_insert, cte_move, cte_swap, cte_t, insert_new_cap, resolve_address_bits};
-pub use super::structures::finaliseCap_ret;
+pub use super::cte::{
+    cte_insert, cte_move, cte_rotate, cte_swap, cte_t, insert_new_cap, resolve_address_bits,
+};
+pub use super::lookup::{
+    ensure_empty_slot, ensure_non_empty_slot, lookup_cap, lookup_cap_and_slot,
+    lookup_empty_target_slot, lookup_non_empty_source_slot, lookup_pivot_slot, lookup_slot,
+    lookup_slot_for_cnode_op, lookup_source_slot, lookup_target_slot,
+};
+pub use super::object::{cap_for_new_object, object_size_bits, ObjectKey};
+#[cfg(feature = "object_index")]
+pub use super::object_index::{slots_of_object, slots_of_object_at, slots_referencing};
+#[cfg(feature = "region_index")]
+pub use super::region_index::{caps_overlapping, enclosing_untyped};
+pub use super::structures::{
+    finaliseCap_ret, lookupCapAndSlot_ret_t, lookupCap_ret_t, lookupSlot_raw_ret_t,
+    lookupSlot_ret_t, resolveAddressBits_ret_t, syscall_error_t,
+};
+pub use super::untyped_invocation::{
+    decode_untyped_invocation, invoke_untyped_retype, UntypedRetype, CONFIG_RETYPE_FAN_OUT_LIMIT,
+};
+pub use super::walk::{slot_cptr, slot_cptrs, CSpaceWalker, SlotCPtrs};
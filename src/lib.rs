@@ -1,17 +1,37 @@
 #![feature(core_intrinsics)]
-#![no_std]
+#![cfg_attr(not(feature = "hosted"), no_std)]
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
 
+#[cfg(any(feature = "region_index", feature = "object_index"))]
+extern crate alloc;
 
+
+mod budget;
 mod cap;
 mod cap_rights;
+mod cnode_invocation;
+mod continuation;
 mod cte;
+mod derivation;
+mod dump;
+mod lookup;
 mod mdb;
+mod object;
+#[cfg(feature = "object_index")]
+mod object_index;
+#[cfg(feature = "region_index")]
+mod region_index;
 mod structures;
+#[cfg(any(feature = "region_index", feature = "object_index"))]
+mod sync;
+mod untyped_invocation;
+mod walk;
 
 pub mod deps;
+#[cfg(feature = "hosted")]
+pub mod hosted;
 pub mod interface;
 
 pub mod compatibility;
\ No newline at end of file
@@ -0,0 +1,164 @@
+//! The lookup family built on `resolve_address_bits`, corresponding to `lookupSlot`, `lookupCap`,
+//! `lookupCapAndSlot` and `lookupSlotForCNodeOp` in seL4.
+//!
+//! `root` is the cap used as the root of the cspace, for a thread it is the cap in its `tcbCTable` slot.
+
+use crate::cap::{cap_t, CapTag};
+use crate::cte::{cte_t, resolve_address_bits};
+use crate::structures::{
+    lookupCapAndSlot_ret_t, lookupCap_ret_t, lookupSlot_raw_ret_t, lookupSlot_ret_t,
+    syscall_error_t,
+};
+use core::intrinsics::unlikely;
+use sel4_common::fault::lookup_fault_t;
+use sel4_common::sel4_config::{seL4_DeleteFirst, wordBits};
+use sel4_common::structures::exception_t;
+use sel4_common::utils::convert_to_type_ref;
+
+/// Find the slot addressed by all the bits of `cap_ptr`.
+pub fn lookup_slot(root: &cap_t, cap_ptr: usize) -> lookupSlot_raw_ret_t {
+    let res_ret = resolve_address_bits(root, cap_ptr, wordBits);
+    lookupSlot_raw_ret_t {
+        status: res_ret.status,
+        slot: res_ret.slot,
+        lookupFault: res_ret.lookupFault,
+    }
+}
+
+/// Find the cap addressed by all the bits of `cap_ptr`.
+pub fn lookup_cap(root: &cap_t, cap_ptr: usize) -> lookupCap_ret_t {
+    let lu_ret = lookup_slot(root, cap_ptr);
+    if unlikely(lu_ret.status != exception_t::EXCEPTION_NONE) {
+        return lookupCap_ret_t {
+            status: lu_ret.status,
+            cap: cap_t::new_null_cap(),
+            lookupFault: lu_ret.lookupFault,
+        };
+    }
+    lookupCap_ret_t {
+        status: exception_t::EXCEPTION_NONE,
+        cap: unsafe { (*lu_ret.slot).cap },
+        lookupFault: lookup_fault_t::default(),
+    }
+}
+
+/// Find both the cap and the slot addressed by all the bits of `cap_ptr`.
+pub fn lookup_cap_and_slot(root: &cap_t, cap_ptr: usize) -> lookupCapAndSlot_ret_t {
+    let lu_ret = lookup_slot(root, cap_ptr);
+    if unlikely(lu_ret.status != exception_t::EXCEPTION_NONE) {
+        return lookupCapAndSlot_ret_t {
+            status: lu_ret.status,
+            cap: cap_t::new_null_cap(),
+            slot: core::ptr::null_mut(),
+            lookupFault: lu_ret.lookupFault,
+        };
+    }
+    lookupCapAndSlot_ret_t {
+        status: exception_t::EXCEPTION_NONE,
+        cap: unsafe { (*lu_ret.slot).cap },
+        slot: lu_ret.slot,
+        lookupFault: lookup_fault_t::default(),
+    }
+}
+
+/// Find the slot addressed by the lowest `depth` bits of `cap_ptr` for a CNode invocation.
+///
+/// Unlike `lookup_slot`, the lookup must consume exactly `depth` bits, and failures are reported as
+/// syscall errors: `seL4_RangeError` for a depth out of `[1, wordBits]`, otherwise `seL4_FailedLookup`.
+pub fn lookup_slot_for_cnode_op(
+    is_source: bool,
+    root: &cap_t,
+    cap_ptr: usize,
+    depth: usize,
+) -> lookupSlot_ret_t {
+    let mut ret = lookupSlot_ret_t::default();
+    if unlikely(root.cap_type_or_null() != CapTag::CapCNodeCap) {
+        ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
+        ret.error = syscall_error_t::new_failed_lookup(is_source, lookup_fault_t::new_root_invalid());
+        return ret;
+    }
+
+    if unlikely(!(1..=wordBits).contains(&depth)) {
+        ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
+        ret.error = syscall_error_t::new_range_error(1, wordBits);
+        return ret;
+    }
+
+    let res_ret = resolve_address_bits(root, cap_ptr, depth);
+    if unlikely(res_ret.status != exception_t::EXCEPTION_NONE) {
+        ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
+        ret.error = syscall_error_t::new_failed_lookup(is_source, res_ret.lookupFault);
+        return ret;
+    }
+
+    if unlikely(res_ret.bitsRemaining != 0) {
+        ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
+        ret.error = syscall_error_t::new_failed_lookup(
+            is_source,
+            lookup_fault_t::new_depth_mismatch(0, res_ret.bitsRemaining),
+        );
+        return ret;
+    }
+    ret.slot = res_ret.slot;
+    ret
+}
+
+#[inline]
+pub fn lookup_source_slot(root: &cap_t, cap_ptr: usize, depth: usize) -> lookupSlot_ret_t {
+    lookup_slot_for_cnode_op(true, root, cap_ptr, depth)
+}
+
+#[inline]
+pub fn lookup_target_slot(root: &cap_t, cap_ptr: usize, depth: usize) -> lookupSlot_ret_t {
+    lookup_slot_for_cnode_op(false, root, cap_ptr, depth)
+}
+
+#[inline]
+pub fn lookup_pivot_slot(root: &cap_t, cap_ptr: usize, depth: usize) -> lookupSlot_ret_t {
+    lookup_slot_for_cnode_op(true, root, cap_ptr, depth)
+}
+
+/// Require the target `slot` to be empty, the error is `seL4_DeleteFirst` otherwise.
+pub fn ensure_empty_slot(slot: &cte_t) -> lookupSlot_ret_t {
+    let mut ret = lookupSlot_ret_t::default();
+    if unlikely(slot.cap.cap_type_or_null() != CapTag::CapNullCap) {
+        ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
+        ret.error = syscall_error_t::new(seL4_DeleteFirst);
+        return ret;
+    }
+    ret.slot = slot as *const cte_t as *mut cte_t;
+    ret
+}
+
+/// Require `slot` found with `depth` bits to hold a cap, the error is a `missing_capability`
+/// lookup failure otherwise.
+pub fn ensure_non_empty_slot(is_source: bool, slot: &cte_t, depth: usize) -> lookupSlot_ret_t {
+    let mut ret = lookupSlot_ret_t::default();
+    if unlikely(slot.cap.cap_type_or_null() == CapTag::CapNullCap) {
+        ret.status = exception_t::EXCEPTION_SYSCALL_ERROR;
+        ret.error =
+            syscall_error_t::new_failed_lookup(is_source, lookup_fault_t::new_missing_cap(depth));
+        return ret;
+    }
+    ret.slot = slot as *const cte_t as *mut cte_t;
+    ret
+}
+
+/// `lookup_target_slot` which also requires the found slot to be empty.
+pub fn lookup_empty_target_slot(root: &cap_t, cap_ptr: usize, depth: usize) -> lookupSlot_ret_t {
+    let lu_ret = lookup_target_slot(root, cap_ptr, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return lu_ret;
+    }
+    ensure_empty_slot(convert_to_type_ref::<cte_t>(lu_ret.slot as usize))
+}
+
+/// `lookup_source_slot` which also requires the found slot to hold a cap.
+pub fn lookup_non_empty_source_slot(root: &cap_t, cap_ptr: usize, depth: usize) -> lookupSlot_ret_t {
+    let lu_ret = lookup_source_slot(root, cap_ptr, depth);
+    if lu_ret.status != exception_t::EXCEPTION_NONE {
+        return lu_ret;
+    }
+    let slot = convert_to_type_ref::<cte_t>(lu_ret.slot as usize);
+    ensure_non_empty_slot(true, slot, depth)
+}
@@ -1,4 +1,8 @@
+use crate::cap::view::UntypedCap;
+use crate::cap::{cap_t, same_object_as, CapTag};
+use crate::cte::cte_t;
 use sel4_common::plus_define_bitfield;
+use sel4_common::utils::convert_to_type_ref;
 
 
 /// Generate from two words, implement a biddirectional link list used to record cap's derivative relationship.
@@ -13,8 +17,242 @@ plus_define_bitfield! {
         new, 0 => {
             mdbNext, get_next, set_next, 1, 2, 37, 2, true,
             mdbRevocable, get_revocable, set_revocable, 1, 1, 1, 0, false,
-            mdbFirstBadged, get_first_badged, set_first_badged, 1, 0, 0, 0, false,
+            mdbFirstBadged, get_first_badged, set_first_badged, 1, 0, 1, 0, false,
             mdbPrev, get_prev, set_prev, 0, 0, 64, 0, false
         }
     }
 }
+
+/// A broken invariant of the mapping database, the fields are addresses of `cte_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MdbViolation {
+    /// `slot.next` is `next`, but `next.prev` is not `slot`.
+    NextPrevMismatch { slot: usize, next: usize },
+    /// `slot.prev` is `prev`, but `prev.next` is not `slot`.
+    PrevNextMismatch { slot: usize, prev: usize },
+    /// Following the links from `slot` never reaches the end of the list.
+    Cycle { slot: usize },
+    /// `child` is a child of `parent` but does not directly follow `parent`'s other descendants.
+    DetachedChild { parent: usize, child: usize },
+    /// `child` descends from the untyped in `parent` but its object is outside the untyped region.
+    UntypedChildOutside { parent: usize, child: usize },
+    /// A badged endpoint or notification cap without `firstBadged`, whose previous slot does not
+    /// hold a cap to the same object with the same badge.
+    MissingFirstBadged { slot: usize },
+    /// A revocable arch cap derived from the cap in the previous slot, arch caps are never revocable.
+    RevocableArchCap { slot: usize },
+}
+
+/// Check the invariants of the mapping database lists containing `root_slots`, every violation is
+/// passed to `report`. Return the number of violations.
+///
+/// The whole list of each root slot is checked, so it only needs one slot of each list. Checking
+/// descendants compares each parent with the rest of its list, which is quadratic in the length of
+/// the list, the checker is meant for debugging.
+pub fn check_invariants<F: FnMut(MdbViolation)>(
+    root_slots: &[*const cte_t],
+    mut report: F,
+) -> usize {
+    let mut count = 0;
+    let mut emit = |violation| {
+        count += 1;
+        report(violation);
+    };
+    for (i, &root) in root_slots.iter().enumerate() {
+        if root.is_null() {
+            continue;
+        }
+        let head = match list_head(root as usize) {
+            Some(head) => head,
+            None => {
+                emit(MdbViolation::Cycle {
+                    slot: root as usize,
+                });
+                continue;
+            }
+        };
+        let checked = root_slots[..i]
+            .iter()
+            .any(|&other| !other.is_null() && list_head(other as usize) == Some(head));
+        if !checked {
+            check_list(head, &mut emit);
+        }
+    }
+    count
+}
+
+/// Panic on the first violation found by `check_invariants`.
+pub fn assert_invariants(root_slots: &[*const cte_t]) {
+    check_invariants(root_slots, |violation| {
+        panic!("mdb invariant violated: {:?}", violation)
+    });
+}
+
+#[inline]
+fn prev_of(slot: usize) -> usize {
+    convert_to_type_ref::<cte_t>(slot).cteMDBNode.get_prev()
+}
+
+#[inline]
+fn next_of(slot: usize) -> usize {
+    convert_to_type_ref::<cte_t>(slot).cteMDBNode.get_next()
+}
+
+/// 沿`prev`找到链表头，存在环时返回`None`（Floyd判环）
+fn list_head(slot: usize) -> Option<usize> {
+    let (mut slow, mut fast) = (slot, slot);
+    loop {
+        if prev_of(fast) == 0 {
+            return Some(fast);
+        }
+        fast = prev_of(fast);
+        if prev_of(fast) == 0 {
+            return Some(fast);
+        }
+        fast = prev_of(fast);
+        slow = prev_of(slow);
+        if slow == fast {
+            return None;
+        }
+    }
+}
+
+/// 沿`next`判断从`head`开始的链表是否有环（Floyd判环）
+fn has_next_cycle(head: usize) -> bool {
+    let (mut slow, mut fast) = (head, head);
+    loop {
+        for _ in 0..2 {
+            fast = next_of(fast);
+            if fast == 0 {
+                return false;
+            }
+        }
+        slow = next_of(slow);
+        if slow == fast {
+            return true;
+        }
+    }
+}
+
+fn check_list<F: FnMut(MdbViolation)>(head: usize, emit: &mut F) {
+    if has_next_cycle(head) {
+        emit(MdbViolation::Cycle { slot: head });
+        return;
+    }
+    let mut slot = head;
+    while slot != 0 {
+        check_links(slot, emit);
+        check_badge(slot, emit);
+        check_arch(slot, emit);
+        check_descendants(slot, emit);
+        slot = next_of(slot);
+    }
+}
+
+fn check_links<F: FnMut(MdbViolation)>(slot: usize, emit: &mut F) {
+    let next = next_of(slot);
+    if next != 0 && prev_of(next) != slot {
+        emit(MdbViolation::NextPrevMismatch { slot, next });
+    }
+    let prev = prev_of(slot);
+    if prev != 0 && next_of(prev) != slot {
+        emit(MdbViolation::PrevNextMismatch { slot, prev });
+    }
+}
+
+fn check_badge<F: FnMut(MdbViolation)>(slot: usize, emit: &mut F) {
+    let cte = convert_to_type_ref::<cte_t>(slot);
+    let badge = match cte.cap.cap_type_or_null() {
+        CapTag::CapEndpointCap => cte.cap.get_ep_badge(),
+        CapTag::CapNotificationCap => cte.cap.get_nf_badge(),
+        _ => return,
+    };
+    if badge == 0 || cte.cteMDBNode.get_first_badged() != 0 {
+        return;
+    }
+    let prev = cte.cteMDBNode.get_prev();
+    let copied = prev != 0 && {
+        let prev_cap = &convert_to_type_ref::<cte_t>(prev).cap;
+        prev_cap.cap_type_or_null() == cte.cap.cap_type_or_null()
+            && same_object_as(prev_cap, &cte.cap)
+            && badge_of(prev_cap) == badge
+    };
+    if !copied {
+        emit(MdbViolation::MissingFirstBadged { slot });
+    }
+}
+
+#[inline]
+fn badge_of(cap: &cap_t) -> usize {
+    match cap.cap_type_or_null() {
+        CapTag::CapEndpointCap => cap.get_ep_badge(),
+        CapTag::CapNotificationCap => cap.get_nf_badge(),
+        _ => 0,
+    }
+}
+
+/// 派生出的`arch cap`不可撤销，`insert_new_cap`放入的原始`cap`除外
+fn check_arch<F: FnMut(MdbViolation)>(slot: usize, emit: &mut F) {
+    let cte = convert_to_type_ref::<cte_t>(slot);
+    if !cte.cap.isArchCap() || cte.cteMDBNode.get_revocable() == 0 {
+        return;
+    }
+    let prev = cte.cteMDBNode.get_prev();
+    if prev != 0 && same_object_as(&convert_to_type_ref::<cte_t>(prev).cap, &cte.cap) {
+        emit(MdbViolation::RevocableArchCap { slot });
+    }
+}
+
+/// The descendants of a slot are the run of slots following it which it is the parent of, as in
+/// `revoke`. Any later slot it is also the parent of is detached from that run. For an untyped,
+/// a slot whose object overlaps the untyped region without being contained is reported as well.
+///
+/// A badged cap minted again with the same badge starts a new run with `firstBadged`, the copies
+/// after it are its descendants, so the check of a badged parent stops there.
+fn check_descendants<F: FnMut(MdbViolation)>(parent: usize, emit: &mut F) {
+    let parent_cte = convert_to_type_ref::<cte_t>(parent);
+    if parent_cte.cteMDBNode.get_revocable() == 0 {
+        return;
+    }
+    let untyped = UntypedCap::try_from(parent_cte.cap).ok();
+    let badge = badge_of(&parent_cte.cap);
+    let mut in_run = true;
+    let mut slot = parent_cte.cteMDBNode.get_next();
+    while slot != 0 {
+        let cte = convert_to_type_ref::<cte_t>(slot);
+        if badge != 0
+            && cte.cteMDBNode.get_first_badged() != 0
+            && badge_of(&cte.cap) == badge
+            && same_object_as(&parent_cte.cap, &cte.cap)
+        {
+            break;
+        }
+        if parent_cte.is_mdb_parent_of(cte) {
+            if !in_run {
+                emit(MdbViolation::DetachedChild {
+                    parent,
+                    child: slot,
+                });
+            }
+        } else if untyped.is_some_and(|untyped| overlaps_untyped(&untyped, &cte.cap)) {
+            emit(MdbViolation::UntypedChildOutside {
+                parent,
+                child: slot,
+            });
+        } else {
+            in_run = false;
+        }
+        slot = cte.cteMDBNode.get_next();
+    }
+}
+
+#[inline]
+fn overlaps_untyped(untyped: &UntypedCap, cap: &cap_t) -> bool {
+    if !cap.get_cap_is_physical() {
+        return false;
+    }
+    match (untyped.as_cap().object_region(), cap.object_region()) {
+        (Some(untyped), Some(object)) => untyped.overlaps(&object),
+        _ => false,
+    }
+}
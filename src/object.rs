@@ -0,0 +1,98 @@
+//! The kernel object types which can be created from untyped memory, and the caps to new objects.
+//!
+//! `ObjectType` is the enum of `sel4_common`, shared with the rest of the kernel. Retype, capDL
+//! loading and boot code all create objects through this table, so that their sizes and initial
+//! caps agree.
+//!
+//! `ObjectKey` identifies the object a cap refers to, whatever the type of the cap.
+
+use crate::cap::region::ObjectRegion;
+use crate::cap::zombie::ZombieKind;
+use crate::cap::{cap_t, CapTag};
+use crate::cap_rights::vm_rights_t;
+use sel4_common::object::ObjectType;
+use sel4_common::sel4_config::{asidInvalid, TCB_OFFSET};
+
+/// The size of an object of type `object_type`, corresponding to `getObjectSize` in seL4.
+///
+/// `user_size` is the size bits of an untyped and the radix of a CNode, it is ignored by the
+/// objects of fixed size. It must be less than `wordBits`.
+#[inline]
+pub fn object_size_bits(object_type: ObjectType, user_size: usize) -> usize {
+    object_type.get_object_size(user_size)
+}
+
+/// The original cap to a new object at `ptr`, with all rights and no badge, guard or mapping,
+/// as created by `createObject` in seL4.
+pub fn cap_for_new_object(
+    object_type: ObjectType,
+    ptr: usize,
+    user_size: usize,
+    is_device: bool,
+) -> cap_t {
+    match object_type {
+        ObjectType::UnytpedObject => cap_t::new_untyped_cap(0, is_device as usize, user_size, ptr),
+        ObjectType::TCBObject => cap_t::new_thread_cap(ptr + TCB_OFFSET),
+        ObjectType::EndpointObject => cap_t::new_endpoint_cap(0, 1, 1, 1, 1, ptr),
+        ObjectType::NotificationObject => cap_t::new_notification_cap(0, 1, 1, ptr),
+        ObjectType::CapTableObject => cap_t::new_cnode_cap(user_size, 0, 0, ptr),
+        ObjectType::GigaPageObject | ObjectType::NormalPageObject | ObjectType::MegaPageObject => {
+            cap_t::new_frame_cap(
+                asidInvalid,
+                ptr,
+                object_type.get_frame_type(),
+                vm_rights_t::VMReadWrite as usize,
+                is_device as usize,
+                0,
+            )
+        }
+        ObjectType::PageTableObject => cap_t::new_page_table_cap(asidInvalid, ptr, 0, 0),
+    }
+}
+
+/// Identifies the kernel object a cap refers to: the caps to the same object have equal keys,
+/// whatever their rights, badge or mapping.
+///
+/// Reply and TCB zombie caps have the key of the thread caps of their TCB, and CNode zombie caps
+/// the key of the CNode caps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectKey {
+    base: usize,
+    size_bits: usize,
+    /// The `CapTag` of the original caps to the object.
+    tag: usize,
+}
+
+impl ObjectKey {
+    /// The key of the object `cap` refers to, `None` if there is no such object.
+    pub fn of_cap(cap: &cap_t) -> Option<ObjectKey> {
+        let region = cap.object_region()?;
+        let tag = match cap.cap_type_or_null() {
+            CapTag::CapReplyCap => CapTag::CapThreadCap,
+            CapTag::CapZombieCap => match cap.get_zombie_kind()? {
+                ZombieKind::Tcb => CapTag::CapThreadCap,
+                ZombieKind::CNode { .. } => CapTag::CapCNodeCap,
+            },
+            tag => tag,
+        };
+        Some(ObjectKey {
+            base: region.base,
+            size_bits: region.size_bits,
+            tag: tag as usize,
+        })
+    }
+
+    /// The key of the object of type `object_type` created at `ptr`, e.g. by retype.
+    #[inline]
+    pub fn of_object(object_type: ObjectType, ptr: usize, user_size: usize) -> ObjectKey {
+        Self::of_cap(&cap_for_new_object(object_type, ptr, user_size, false)).unwrap()
+    }
+
+    #[inline]
+    pub fn region(&self) -> ObjectRegion {
+        ObjectRegion {
+            base: self.base,
+            size_bits: self.size_bits,
+        }
+    }
+}
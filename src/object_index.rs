@@ -0,0 +1,85 @@
+//! An index of the slots by the object their cap refers to, enabled by the `object_index` feature.
+//!
+//! The cte operations (`cte_insert`, `insert_new_cap`, `cte_move`, `cte_swap` and deletion) keep it
+//! up to date, the same way as `region_index`. Unlike `cte_t::object_slots`, it also finds the
+//! caps to an object which are not in one run of the MDB list, such as reply caps, and it only
+//! needs the object, not a slot holding a cap to it.
+//!
+//! Caps written into slots directly, without the cte operations, are not indexed. Like those of
+//! `region_index`, the entries of the slots of a hosted `Arena` go with its memory.
+
+use crate::cap::cap_t;
+use crate::cte::cte_t;
+use crate::object::ObjectKey;
+use crate::sync::SpinLock;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use sel4_common::object::ObjectType;
+use sel4_common::utils::convert_to_type_ref;
+
+/// `(key, slot)`
+static INDEX: SpinLock<BTreeSet<(ObjectKey, usize)>> = SpinLock::new(BTreeSet::new());
+
+/// 更新`slot`中的`cap`由`old`变为`new`后的索引
+pub(crate) fn cap_changed(slot: *const cte_t, old: &cap_t, new: &cap_t) {
+    let old_key = ObjectKey::of_cap(old);
+    let new_key = ObjectKey::of_cap(new);
+    if old_key == new_key {
+        return;
+    }
+    INDEX.with(|index| {
+        if let Some(key) = old_key {
+            index.remove(&(key, slot as usize));
+        }
+        if let Some(key) = new_key {
+            index.insert((key, slot as usize));
+        }
+    })
+}
+
+/// The slots in `[start, end)` with an entry in the index.
+#[cfg(feature = "hosted")]
+pub(crate) fn slots_in(start: usize, end: usize) -> Vec<usize> {
+    INDEX.with(|index| {
+        index
+            .iter()
+            .map(|&(_, slot)| slot)
+            .filter(|slot| (start..end).contains(slot))
+            .collect()
+    })
+}
+
+/// Remove the entries of the slots in `[start, end)`, whatever cap they hold now.
+#[cfg(feature = "hosted")]
+pub(crate) fn forget_slots(start: usize, end: usize) {
+    INDEX.with(|index| index.retain(|&(_, slot)| !(start..end).contains(&slot)))
+}
+
+/// The slots holding a cap to the object `key`, ordered by address.
+pub fn slots_of_object(key: ObjectKey) -> Vec<&'static cte_t> {
+    INDEX.with(|index| {
+        index
+            .range((key, 0)..=(key, usize::MAX))
+            .map(|&(_, slot)| convert_to_type_ref::<cte_t>(slot))
+            .collect()
+    })
+}
+
+/// The slots holding a cap to the same object as `cap`, including reply, badged and zombie caps.
+/// Empty if the cap has no object.
+pub fn slots_referencing(cap: &cap_t) -> Vec<&'static cte_t> {
+    match ObjectKey::of_cap(cap) {
+        Some(key) => slots_of_object(key),
+        None => Vec::new(),
+    }
+}
+
+/// The slots holding a cap to the object of type `object_type` at `ptr`.
+#[inline]
+pub fn slots_of_object_at(
+    object_type: ObjectType,
+    ptr: usize,
+    user_size: usize,
+) -> Vec<&'static cte_t> {
+    slots_of_object(ObjectKey::of_object(object_type, ptr, user_size))
+}
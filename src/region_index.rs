@@ -0,0 +1,140 @@
+//! An index of the untyped and frame caps by the physical region of their object, enabled by the
+//! `region_index` feature.
+//!
+//! The cte operations (`cte_insert`, `insert_new_cap`, `cte_move`, `cte_swap` and deletion) keep it
+//! up to date, so overlap and enclosing untyped queries do not walk the MDB. Regions are naturally
+//! aligned, so they are kept ordered by size and then base: a query looks up each size once, and
+//! costs `O(wordBits * log n)` plus the number of caps returned.
+//!
+//! Caps written into slots directly, without the cte operations, are not indexed. A hosted `Arena`
+//! removes the entries of its slots when it is dropped or restored.
+
+use crate::cap::{cap_t, CapTag};
+use crate::cte::cte_t;
+use crate::sync::SpinLock;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use sel4_common::sel4_config::wordBits;
+use sel4_common::utils::convert_to_type_ref;
+use sel4_common::MASK;
+
+/// `(size_bits, base, slot)`
+type Entry = (usize, usize, usize);
+
+struct RegionIndex {
+    untyped: BTreeSet<Entry>,
+    frames: BTreeSet<Entry>,
+}
+
+impl RegionIndex {
+    fn set_of(&mut self, cap: &cap_t) -> Option<&mut BTreeSet<Entry>> {
+        match cap.cap_type_or_null() {
+            CapTag::CapUntypedCap => Some(&mut self.untyped),
+            CapTag::CapFrameCap => Some(&mut self.frames),
+            _ => None,
+        }
+    }
+}
+
+static INDEX: SpinLock<RegionIndex> = SpinLock::new(RegionIndex {
+    untyped: BTreeSet::new(),
+    frames: BTreeSet::new(),
+});
+
+#[inline]
+fn is_indexed(cap: &cap_t) -> bool {
+    matches!(
+        cap.cap_type_or_null(),
+        CapTag::CapUntypedCap | CapTag::CapFrameCap
+    )
+}
+
+#[inline]
+fn entry_of(cap: &cap_t, slot: *const cte_t) -> Entry {
+    let region = cap.object_region().unwrap();
+    (region.size_bits, region.base, slot as usize)
+}
+
+/// 更新`slot`中的`cap`由`old`变为`new`后的索引
+pub(crate) fn cap_changed(slot: *const cte_t, old: &cap_t, new: &cap_t) {
+    if !is_indexed(old) && !is_indexed(new) {
+        return;
+    }
+    INDEX.with(|index| {
+        if let Some(set) = index.set_of(old) {
+            set.remove(&entry_of(old, slot));
+        }
+        if let Some(set) = index.set_of(new) {
+            set.insert(entry_of(new, slot));
+        }
+    })
+}
+
+/// The slots in `[start, end)` with an entry in the index.
+#[cfg(feature = "hosted")]
+pub(crate) fn slots_in(start: usize, end: usize) -> Vec<usize> {
+    INDEX.with(|index| {
+        let slots = index.untyped.iter().chain(index.frames.iter());
+        slots
+            .map(|&(_, _, slot)| slot)
+            .filter(|slot| (start..end).contains(slot))
+            .collect()
+    })
+}
+
+/// Remove the entries of the slots in `[start, end)`, whatever cap they hold now.
+#[cfg(feature = "hosted")]
+pub(crate) fn forget_slots(start: usize, end: usize) {
+    let kept = |&(_, _, slot): &Entry| !(start..end).contains(&slot);
+    INDEX.with(|index| {
+        index.untyped.retain(kept);
+        index.frames.retain(kept);
+    })
+}
+
+/// The slots of the indexed caps whose object overlaps `[start, end)`.
+fn overlapping(set: &BTreeSet<Entry>, start: usize, end: usize, found: &mut Vec<&'static cte_t>) {
+    for size_bits in 0..wordBits {
+        let low = start & !MASK!(size_bits);
+        for &(_, _, slot) in set.range((size_bits, low, 0)..(size_bits, end, 0)) {
+            found.push(convert_to_type_ref::<cte_t>(slot));
+        }
+    }
+}
+
+/// The untyped and frame caps whose object overlaps the physical range `[start, end)`, the
+/// untyped caps first, each group ordered by size and then address.
+pub fn caps_overlapping(start: usize, end: usize) -> Vec<&'static cte_t> {
+    let mut found = Vec::new();
+    if start >= end {
+        return found;
+    }
+    INDEX.with(|index| {
+        overlapping(&index.untyped, start, end, &mut found);
+        overlapping(&index.frames, start, end, &mut found);
+    });
+    found
+}
+
+/// The smallest untyped cap whose memory contains the physical range `[start, end)`, e.g. to
+/// check that a device range is covered by device untyped memory.
+pub fn enclosing_untyped(start: usize, end: usize) -> Option<&'static cte_t> {
+    if start >= end {
+        return None;
+    }
+    INDEX.with(|index| {
+        for size_bits in 0..wordBits {
+            let base = start & !MASK!(size_bits);
+            if end - 1 - base > MASK!(size_bits) {
+                continue;
+            }
+            let mut slots = index
+                .untyped
+                .range((size_bits, base, 0)..=(size_bits, base, usize::MAX));
+            if let Some(&(_, _, slot)) = slots.next() {
+                return Some(convert_to_type_ref::<cte_t>(slot));
+            }
+        }
+        None
+    })
+}
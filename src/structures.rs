@@ -1,4 +1,8 @@
 use crate::cte::cte_t;
+use sel4_common::fault::lookup_fault_t;
+use sel4_common::sel4_config::{
+    seL4_FailedLookup, seL4_InvalidArgument, seL4_NotEnoughMemory, seL4_RangeError,
+};
 use sel4_common::structures::exception_t;
 
 use super::cap::cap_t;
@@ -46,12 +50,17 @@ impl Default for finaliseCap_ret {
     }
 }
 
+/// Result of `resolve_address_bits`.
+///
+/// lookupFault: When status is `EXCEPTION_LOOKUP_FAULT`, records why the lookup failed
+/// (invalid root, guard mismatch or depth mismatch), so that a cap fault can be built for user space.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct resolveAddressBits_ret_t {
     pub status: exception_t,
     pub slot: *mut cte_t,
     pub bitsRemaining: usize,
+    pub lookupFault: lookup_fault_t,
 }
 
 impl Default for resolveAddressBits_ret_t {
@@ -61,6 +70,155 @@ impl Default for resolveAddressBits_ret_t {
             status: exception_t::EXCEPTION_NONE,
             slot: 0 as *mut cte_t,
             bitsRemaining: 0,
+            lookupFault: lookup_fault_t::default(),
+        }
+    }
+}
+
+/// Error details of a failed syscall, corresponding to `current_syscall_error` in seL4.
+///
+/// _type: One of the `seL4_*` error codes in `sel4_config`.
+///
+/// lookupFault: When _type is `seL4_FailedLookup`, records why the lookup failed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct syscall_error_t {
+    pub _type: usize,
+    pub invalidArgumentNumber: usize,
+    pub invalidCapNumber: usize,
+    pub rangeErrorMin: usize,
+    pub rangeErrorMax: usize,
+    pub memoryLeft: usize,
+    pub failedLookupWasSource: usize,
+    pub lookupFault: lookup_fault_t,
+}
+
+impl syscall_error_t {
+    #[inline]
+    pub fn new(_type: usize) -> Self {
+        syscall_error_t {
+            _type,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn new_failed_lookup(is_source: bool, lookup_fault: lookup_fault_t) -> Self {
+        syscall_error_t {
+            _type: seL4_FailedLookup,
+            failedLookupWasSource: is_source as usize,
+            lookupFault: lookup_fault,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn new_invalid_argument(argument_number: usize) -> Self {
+        syscall_error_t {
+            _type: seL4_InvalidArgument,
+            invalidArgumentNumber: argument_number,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn new_not_enough_memory(memory_left: usize) -> Self {
+        syscall_error_t {
+            _type: seL4_NotEnoughMemory,
+            memoryLeft: memory_left,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn new_range_error(min: usize, max: usize) -> Self {
+        syscall_error_t {
+            _type: seL4_RangeError,
+            rangeErrorMin: min,
+            rangeErrorMax: max,
+            ..Default::default()
+        }
+    }
+}
+
+/// Result of `lookup_slot`, the status is `EXCEPTION_LOOKUP_FAULT` when failed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct lookupSlot_raw_ret_t {
+    pub status: exception_t,
+    pub slot: *mut cte_t,
+    pub lookupFault: lookup_fault_t,
+}
+
+impl Default for lookupSlot_raw_ret_t {
+    #[inline]
+    fn default() -> Self {
+        lookupSlot_raw_ret_t {
+            status: exception_t::EXCEPTION_NONE,
+            slot: core::ptr::null_mut(),
+            lookupFault: lookup_fault_t::default(),
+        }
+    }
+}
+
+/// Result of the lookups used by CNode operations, the status is `EXCEPTION_SYSCALL_ERROR` when failed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct lookupSlot_ret_t {
+    pub status: exception_t,
+    pub slot: *mut cte_t,
+    pub error: syscall_error_t,
+}
+
+impl Default for lookupSlot_ret_t {
+    #[inline]
+    fn default() -> Self {
+        lookupSlot_ret_t {
+            status: exception_t::EXCEPTION_NONE,
+            slot: core::ptr::null_mut(),
+            error: syscall_error_t::default(),
+        }
+    }
+}
+
+/// Result of `lookup_cap`, cap is null_cap when failed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct lookupCap_ret_t {
+    pub status: exception_t,
+    pub cap: cap_t,
+    pub lookupFault: lookup_fault_t,
+}
+
+impl Default for lookupCap_ret_t {
+    #[inline]
+    fn default() -> Self {
+        lookupCap_ret_t {
+            status: exception_t::EXCEPTION_NONE,
+            cap: cap_t::default(),
+            lookupFault: lookup_fault_t::default(),
+        }
+    }
+}
+
+/// Result of `lookup_cap_and_slot`, cap is null_cap and slot is null when failed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct lookupCapAndSlot_ret_t {
+    pub status: exception_t,
+    pub cap: cap_t,
+    pub slot: *mut cte_t,
+    pub lookupFault: lookup_fault_t,
+}
+
+impl Default for lookupCapAndSlot_ret_t {
+    #[inline]
+    fn default() -> Self {
+        lookupCapAndSlot_ret_t {
+            status: exception_t::EXCEPTION_NONE,
+            cap: cap_t::default(),
+            slot: core::ptr::null_mut(),
+            lookupFault: lookup_fault_t::default(),
         }
     }
 }
@@ -0,0 +1,38 @@
+//! A minimal lock for the global indexes of the optional features.
+//!
+//! The kernel already serialises cspace operations, so the lock is normally uncontended, it makes
+//! the indexes safe to use from several threads in hosted mode.
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub(crate) struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Run `f` with the value locked, `f` must not take the lock again.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        let ret = f(unsafe { &mut *self.value.get() });
+        self.locked.store(false, Ordering::Release);
+        ret
+    }
+}
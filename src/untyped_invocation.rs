@@ -0,0 +1,252 @@
+//! Decoding and performing the retype invocation of an untyped object, corresponding to
+//! `decodeUntypedInvocation`, `invokeUntyped_Retype` and `createNewObjects` in seL4.
+//!
+//! Objects are carved from the untyped memory after its free index, each one aligned to its size.
+//! The memory of each object is handed to `CSpaceHooks::init_object`, and then its cap is inserted
+//! into the destination CNode as a child of the untyped cap.
+//!
+//! When the untyped has no children left, e.g. after a revoke, the retype first resets it with
+//! `cte_t::reset_untyped`, so its whole memory can be used again.
+
+use crate::budget::WorkBudget;
+use crate::cap::view::{CNodeCap, UntypedCap};
+use crate::cap::{cap_t, CapTag};
+use crate::cte::{convert_to_slot, cte_t, insert_new_cap};
+use crate::deps::CSpaceHooks;
+use crate::lookup::{ensure_empty_slot, lookup_target_slot};
+use crate::object::{cap_for_new_object, object_size_bits};
+use crate::structures::syscall_error_t;
+#[cfg(feature = "extern_deps")]
+use crate::{budget::KernelPreemptionPoint, deps::ExternHooks};
+use sel4_common::fault::lookup_fault_t;
+use sel4_common::message_info::MessageLabel;
+use sel4_common::object::ObjectType;
+use sel4_common::sel4_config::{
+    seL4_IllegalOperation, seL4_MaxUntypedBits, seL4_MinUntypedBits, seL4_TruncatedMessage,
+    wordBits, CONFIG_RESET_CHUNK_BITS,
+};
+use sel4_common::structures::exception_t;
+use sel4_common::{BIT, MASK, ROUND_UP};
+
+/// The most objects a single retype can create.
+pub const CONFIG_RETYPE_FAN_OUT_LIMIT: usize = 256;
+
+/// A decoded untyped retype, the arguments have been checked and the destination slots are empty.
+#[derive(Clone, Copy, Debug)]
+pub struct UntypedRetype {
+    /// The slot of the untyped cap, parent of the new caps.
+    pub slot: *mut cte_t,
+    /// Whether the untyped has no children and is reset before the objects are created.
+    pub reset: bool,
+    pub object_type: ObjectType,
+    /// The size bits of an untyped object, or the radix of a CNode.
+    pub user_size: usize,
+    /// The address of the first object.
+    pub region_base: usize,
+    /// The first slot of the destination CNode.
+    pub dest_cnode: *mut cte_t,
+    pub dest_offset: usize,
+    /// The number of objects to create, one in each slot from `dest_offset`.
+    pub dest_length: usize,
+    pub device_memory: bool,
+}
+
+/// Decode an untyped invocation.
+///
+/// `slot` holds the invoked untyped cap, `args` are the message words of the invocation and
+/// `extra_caps` are the caps in the extra cap slots, the first one being the root of the lookup of
+/// the destination CNode.
+pub fn decode_untyped_invocation(
+    label: MessageLabel,
+    slot: *mut cte_t,
+    args: &[usize],
+    extra_caps: &[cap_t],
+) -> Result<UntypedRetype, syscall_error_t> {
+    if label != MessageLabel::UntypedRetype {
+        return Err(syscall_error_t::new(seL4_IllegalOperation));
+    }
+    let untyped = match UntypedCap::try_from(convert_to_slot(slot).cap) {
+        Ok(untyped) => untyped,
+        Err(_) => return Err(syscall_error_t::new(seL4_IllegalOperation)),
+    };
+    if args.len() < 6 || extra_caps.is_empty() {
+        return Err(syscall_error_t::new(seL4_TruncatedMessage));
+    }
+    let new_type = args[0];
+    let user_size = args[1];
+    let node_index = args[2];
+    let node_depth = args[3];
+    let node_offset = args[4];
+    let node_window = args[5];
+    let root = extra_caps[0];
+
+    let object_type = match ObjectType::from_usize(new_type) {
+        Some(object_type) => object_type,
+        None => return Err(syscall_error_t::new_invalid_argument(0)),
+    };
+    if user_size >= wordBits || object_size_bits(object_type, user_size) > seL4_MaxUntypedBits {
+        return Err(syscall_error_t::new_range_error(0, seL4_MaxUntypedBits));
+    }
+    let object_size = object_size_bits(object_type, user_size);
+    if object_type == ObjectType::CapTableObject && user_size == 0 {
+        return Err(syscall_error_t::new_invalid_argument(1));
+    }
+    if object_type == ObjectType::UnytpedObject && user_size < seL4_MinUntypedBits {
+        return Err(syscall_error_t::new_invalid_argument(1));
+    }
+
+    let node_cap = if node_depth == 0 {
+        root
+    } else {
+        let lu_ret = lookup_target_slot(&root, node_index, node_depth);
+        if lu_ret.status != exception_t::EXCEPTION_NONE {
+            return Err(lu_ret.error);
+        }
+        convert_to_slot(lu_ret.slot).cap
+    };
+    let cnode = match CNodeCap::try_from(node_cap) {
+        Ok(cnode) => cnode,
+        Err(_) => {
+            return Err(syscall_error_t::new_failed_lookup(
+                false,
+                lookup_fault_t::new_missing_cap(node_depth),
+            ))
+        }
+    };
+    let node_size = BIT!(cnode.radix());
+    if node_offset > node_size - 1 {
+        return Err(syscall_error_t::new_range_error(0, node_size - 1));
+    }
+    if !(1..=CONFIG_RETYPE_FAN_OUT_LIMIT).contains(&node_window) {
+        return Err(syscall_error_t::new_range_error(
+            1,
+            CONFIG_RETYPE_FAN_OUT_LIMIT,
+        ));
+    }
+    if node_window > node_size - node_offset {
+        return Err(syscall_error_t::new_range_error(1, node_size - node_offset));
+    }
+    let dest_cnode = cnode.ptr() as *mut cte_t;
+    for i in node_offset..node_offset + node_window {
+        let status = ensure_empty_slot(convert_to_slot(unsafe { dest_cnode.add(i) }));
+        if status.status != exception_t::EXCEPTION_NONE {
+            return Err(status.error);
+        }
+    }
+
+    // 没有子对象时，`untyped`会被重置，从头开始分配
+    let reset = convert_to_slot(slot).ensure_no_children() == exception_t::EXCEPTION_NONE;
+    let free_index = if reset { 0 } else { untyped.free_index() };
+    let free_offset = free_index << seL4_MinUntypedBits;
+    let free_bytes = BIT!(untyped.block_size()) - free_offset;
+    if (free_bytes >> object_size) < node_window {
+        return Err(syscall_error_t::new_not_enough_memory(free_bytes));
+    }
+    let device_memory = untyped.is_device();
+    // 设备内存中只能创建`frame`和`untyped`
+    if device_memory && !object_type.is_arch_type() && object_type != ObjectType::UnytpedObject {
+        return Err(syscall_error_t::new_invalid_argument(1));
+    }
+    Ok(UntypedRetype {
+        slot,
+        reset,
+        object_type,
+        user_size,
+        region_base: ROUND_UP!(untyped.ptr() + free_offset, object_size),
+        dest_cnode,
+        dest_offset: node_offset,
+        dest_length: node_window,
+        device_memory,
+    })
+}
+
+/// Perform a decoded untyped retype: reset the untyped if needed, advance its free index, then
+/// initialise each object through `hooks` and insert its cap.
+///
+/// Only the reset can be preempted, the retype is then restarted from the decoding.
+pub fn invoke_untyped_retype<H: CSpaceHooks, B: WorkBudget>(
+    retype: &UntypedRetype,
+    hooks: &mut H,
+    budget: &mut B,
+) -> exception_t {
+    let slot = convert_to_slot(retype.slot);
+    assert_eq!(slot.cap.cap_type_or_null(), CapTag::CapUntypedCap);
+    if retype.reset {
+        let status = slot.reset_untyped_with(hooks, budget);
+        if status != exception_t::EXCEPTION_NONE {
+            return status;
+        }
+    }
+    let object_size = object_size_bits(retype.object_type, retype.user_size);
+    let free_ref = retype.region_base + (retype.dest_length << object_size);
+    let free_index = (free_ref - slot.cap.get_untyped_ptr()) >> seL4_MinUntypedBits;
+    slot.cap.set_untyped_free_index(free_index);
+
+    for i in 0..retype.dest_length {
+        let ptr = retype.region_base + (i << object_size);
+        hooks.init_object(
+            retype.object_type,
+            ptr,
+            retype.user_size,
+            retype.device_memory,
+        );
+        let cap = cap_for_new_object(
+            retype.object_type,
+            ptr,
+            retype.user_size,
+            retype.device_memory,
+        );
+        let dest_slot = unsafe { retype.dest_cnode.add(retype.dest_offset + i) };
+        insert_new_cap(slot, convert_to_slot(dest_slot), &cap);
+    }
+    exception_t::EXCEPTION_NONE
+}
+
+impl cte_t {
+    /// Reset the untyped cap in this slot, which must have no children: clear its memory and set
+    /// its free index back to 0.
+    #[cfg(feature = "extern_deps")]
+    #[inline]
+    pub fn reset_untyped(&mut self) -> exception_t {
+        self.reset_untyped_with(&mut ExternHooks, &mut KernelPreemptionPoint)
+    }
+
+    /// 与`reset_untyped`相同，但通过`hooks`清除内存，每清除一块记一个工作量
+    ///
+    /// The memory is cleared in `CONFIG_RESET_CHUNK_BITS` chunks from the top, and the free index
+    /// is lowered after each chunk, so a preempted reset continues where it stopped. Device memory
+    /// is not cleared.
+    pub fn reset_untyped_with<H: CSpaceHooks, B: WorkBudget>(
+        &mut self,
+        hooks: &mut H,
+        budget: &mut B,
+    ) -> exception_t {
+        let untyped = UntypedCap::try_from(self.cap).expect("reset of a non-untyped cap");
+        let region_base = untyped.ptr();
+        let block_size = untyped.block_size();
+        let offset = untyped.free_index() << seL4_MinUntypedBits;
+        if offset == 0 {
+            return exception_t::EXCEPTION_NONE;
+        }
+        if untyped.is_device() || block_size < CONFIG_RESET_CHUNK_BITS {
+            if !untyped.is_device() {
+                hooks.clear_memory(region_base, block_size);
+            }
+            self.cap.set_untyped_free_index(0);
+            return exception_t::EXCEPTION_NONE;
+        }
+        let mut chunk = (offset - 1) & !MASK!(CONFIG_RESET_CHUNK_BITS);
+        loop {
+            hooks.clear_memory(region_base + chunk, CONFIG_RESET_CHUNK_BITS);
+            self.cap
+                .set_untyped_free_index(chunk >> seL4_MinUntypedBits);
+            if budget.charge(1).is_err() {
+                return exception_t::EXCEPTION_PREEMTED;
+            }
+            if chunk == 0 {
+                return exception_t::EXCEPTION_NONE;
+            }
+            chunk -= BIT!(CONFIG_RESET_CHUNK_BITS);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Enumerate every slot reachable from a root CNode cap, with the CPtr and depth addressing it.
+//!
+//! The walk follows nested CNode caps with the same guard and radix arithmetic as
+//! `resolve_address_bits`, so `lookup_slot_for_cnode_op(false, root, cptr, depth)` finds the slot
+//! yielded with `(cptr, depth)`.
+
+use crate::cap::{cap_t, CapTag};
+use crate::cte::cte_t;
+use sel4_common::sel4_config::{seL4_SlotBits, wordBits};
+use sel4_common::utils::convert_to_type_ref;
+use sel4_common::{BIT, MASK};
+
+/// Every level consumes at least one bit of the CPtr, so a walk is never deeper than this.
+const MAX_WALK_DEPTH: usize = wordBits;
+
+#[derive(Clone, Copy, Default)]
+struct Frame {
+    cnode_ptr: usize,
+    radix: usize,
+    /// The bits of the CPtr above the radix bits of this level, guard included.
+    prefix: usize,
+    /// The depth of the slots of this level.
+    depth: usize,
+    next_index: usize,
+}
+
+/// Iterator over `(cptr, depth, slot)` of every non-null slot reachable from a root CNode cap.
+///
+/// A slot holding a CNode cap is yielded, and then the slots of that CNode when they can be
+/// addressed: the guard fits in the guard size and the CPtr does not exceed `wordBits`. A CNode
+/// cap referring to a CNode already on the path to it forms a cycle, it is yielded but not
+/// entered, and counted in `cycles`.
+pub struct CSpaceWalker {
+    stack: [Frame; MAX_WALK_DEPTH],
+    len: usize,
+    cycles: usize,
+    /// Whether the last slot yielded is a CNode cap which has been entered.
+    entered: bool,
+}
+
+impl CSpaceWalker {
+    /// Start a walk from `root`, nothing is yielded when it is not a CNode cap.
+    pub fn new(root: &cap_t) -> Self {
+        let mut walker = CSpaceWalker {
+            stack: [Frame::default(); MAX_WALK_DEPTH],
+            len: 0,
+            cycles: 0,
+            entered: false,
+        };
+        if root.cap_type_or_null() == CapTag::CapCNodeCap {
+            walker.enter(root, 0, 0);
+        }
+        walker
+    }
+
+    /// The number of CNode caps found so far that were not entered because they form a cycle.
+    #[inline]
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// 如果可以按`resolve_address_bits`的方式寻址，将`cnode`压栈
+    fn enter(&mut self, cnode: &cap_t, cptr: usize, depth: usize) {
+        let radix = cnode.get_cnode_radix();
+        let guard_size = cnode.get_cnode_guard_size();
+        let guard = cnode.get_cnode_guard();
+        let level_bits = radix + guard_size;
+        if level_bits == 0 || depth + level_bits > wordBits || guard & !MASK!(guard_size) != 0 {
+            return;
+        }
+        let cnode_ptr = cnode.get_cnode_ptr();
+        if self.stack[..self.len]
+            .iter()
+            .any(|frame| frame.cnode_ptr == cnode_ptr)
+        {
+            self.cycles += 1;
+            return;
+        }
+        self.stack[self.len] = Frame {
+            cnode_ptr,
+            radix,
+            prefix: (cptr << guard_size) | guard,
+            depth: depth + level_bits,
+            next_index: 0,
+        };
+        self.len += 1;
+        self.entered = true;
+    }
+}
+
+impl Iterator for CSpaceWalker {
+    type Item = (usize, usize, &'static cte_t);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entered = false;
+        while self.len > 0 {
+            let frame = &mut self.stack[self.len - 1];
+            if frame.next_index == BIT!(frame.radix) {
+                self.len -= 1;
+                continue;
+            }
+            let index = frame.next_index;
+            frame.next_index += 1;
+            let slot = convert_to_type_ref::<cte_t>(frame.cnode_ptr + (index << seL4_SlotBits));
+            if slot.cap.cap_type_or_null() == CapTag::CapNullCap {
+                continue;
+            }
+            let cptr = (frame.prefix << frame.radix) | index;
+            let depth = frame.depth;
+            if slot.cap.cap_type_or_null() == CapTag::CapCNodeCap {
+                self.enter(&slot.cap, cptr, depth);
+            }
+            return Some((cptr, depth, slot));
+        }
+        None
+    }
+}
+
+/// Iterator over the `(cptr, depth)` pairs addressing one slot, returned by `slot_cptrs`.
+pub struct SlotCPtrs {
+    walker: CSpaceWalker,
+    slot: usize,
+    root_checked: bool,
+}
+
+impl SlotCPtrs {
+    /// 如果`slot`在`frame`对应的`CNode`中，返回其`(cptr, depth)`
+    fn address_in(&self, frame: &Frame) -> Option<(usize, usize)> {
+        let offset = self.slot.wrapping_sub(frame.cnode_ptr);
+        let index = offset >> seL4_SlotBits;
+        if offset & MASK!(seL4_SlotBits) != 0 || index >= BIT!(frame.radix) {
+            return None;
+        }
+        Some(((frame.prefix << frame.radix) | index, frame.depth))
+    }
+}
+
+impl Iterator for SlotCPtrs {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.root_checked {
+            self.root_checked = true;
+            if self.walker.len > 0 {
+                if let Some(found) = self.address_in(&self.walker.stack[0]) {
+                    return Some(found);
+                }
+            }
+        }
+        while self.walker.next().is_some() {
+            if self.walker.entered {
+                if let Some(found) = self.address_in(&self.walker.stack[self.walker.len - 1]) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// All the `(cptr, depth)` pairs under which `slot` is addressable from `root`, in walk order.
+///
+/// A slot can have several when its CNode is reachable through more than one CNode cap. The slot
+/// may be empty, e.g. the destination of a lookup.
+pub fn slot_cptrs(root: &cap_t, slot: *const cte_t) -> SlotCPtrs {
+    SlotCPtrs {
+        walker: CSpaceWalker::new(root),
+        slot: slot as usize,
+        root_checked: false,
+    }
+}
+
+/// The shortest `(cptr, depth)` addressing `slot` from `root`, `None` if it is not reachable.
+pub fn slot_cptr(root: &cap_t, slot: *const cte_t) -> Option<(usize, usize)> {
+    slot_cptrs(root, slot).min_by_key(|&(_, depth)| depth)
+}
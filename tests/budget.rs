@@ -0,0 +1,48 @@
+//! `FixedBudget` and `Unbounded` charged directly.
+#![cfg(feature = "hosted")]
+
+use sel4_cspace::interface::{FixedBudget, Preempted, Unbounded, WorkBudget};
+
+#[test]
+fn fixed_budget_boundary() {
+    let mut budget = FixedBudget::new(3);
+    assert_eq!(budget.charge(1), Ok(()));
+    assert_eq!(budget.remaining(), 2);
+    // 用完剩余的全部单位即被抢占
+    assert_eq!(budget.charge(2), Err(Preempted));
+    assert_eq!(budget.remaining(), 0);
+    assert_eq!(budget.charge(0), Err(Preempted));
+
+    let mut budget = FixedBudget::new(3);
+    assert_eq!(budget.charge(2), Ok(()));
+    assert_eq!(budget.charge(5), Err(Preempted));
+    assert_eq!(budget.remaining(), 0);
+
+    let mut budget = FixedBudget::new(0);
+    assert_eq!(budget.charge(0), Err(Preempted));
+    let mut budget = FixedBudget::new(1);
+    assert_eq!(budget.charge(0), Ok(()));
+    assert_eq!(budget.charge(1), Err(Preempted));
+}
+
+#[test]
+fn fixed_budget_refill() {
+    let mut budget = FixedBudget::new(2);
+    assert_eq!(budget.charge(5), Err(Preempted));
+    budget.refill(4);
+    assert_eq!(budget.remaining(), 4);
+    assert_eq!(budget.charge(1), Ok(()));
+    // `refill`设置剩余的单位，不是累加
+    budget.refill(2);
+    assert_eq!(budget.remaining(), 2);
+    assert_eq!(budget.charge(1), Ok(()));
+    assert_eq!(budget.charge(1), Err(Preempted));
+}
+
+#[test]
+fn unbounded_never_preempts() {
+    let mut budget = Unbounded;
+    for units in [0, 1, usize::MAX] {
+        assert_eq!(budget.charge(units), Ok(()));
+    }
+}
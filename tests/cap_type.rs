@@ -0,0 +1,80 @@
+//! Caps whose type field is not a valid `CapTag`, as read from corrupted memory.
+#![cfg(feature = "hosted")]
+
+use core::ptr::null_mut;
+use sel4_common::message_info::MessageLabel;
+use sel4_common::sel4_config::seL4_FailedLookup;
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, check_invariants, cte_insert, decode_cnode_invocation, same_object_as, same_region_as,
+    seL4_CapRights_t, CapTag, Unbounded,
+};
+
+/// `tag` is not a valid `CapTag`.
+fn with_tag(cap: &cap_t, tag: usize) -> cap_t {
+    let mut cap = *cap;
+    cap.words[0] = (cap.words[0] & !(0x1f << 59)) | (tag << 59);
+    cap
+}
+
+#[test]
+fn unknown_tags_read_as_null() {
+    let mut arena = Arena::new(16);
+    let ep = arena.new_endpoint();
+    for tag in [5, 7, 9, 15, 17, 19, 21, 31] {
+        let cap = with_tag(&ep, tag);
+        assert_eq!(cap.try_cap_type(), Err(tag));
+        assert_eq!(cap.cap_type_or_null(), CapTag::CapNullCap);
+        assert_eq!(cap.get_cap_type(), CapTag::CapNullCap);
+        assert!(!cap.isArchCap());
+        assert!(cap.object_region().is_none());
+        assert!(!same_region_as(&cap, &ep) && !same_region_as(&ep, &cap));
+        assert!(!same_object_as(&cap, &ep) && !same_object_as(&ep, &cap));
+        assert_eq!(
+            cap.mask_rights(seL4_CapRights_t::from_word(0)).words,
+            cap.words
+        );
+        assert_eq!(cap.update_data(false, 7).words, cap.words);
+    }
+}
+
+#[test]
+fn unknown_tags_in_slots() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(4, 60, 0);
+    let original = cnode_slot(&root, 0);
+    original.cap = arena.new_endpoint();
+    original.cteMDBNode.set_revocable(1);
+    let ep = original.cap;
+    cte_insert(&ep, original, cnode_slot(&root, 1));
+    cte_insert(&ep, cnode_slot(&root, 1), cnode_slot(&root, 2));
+    let corrupted = cnode_slot(&root, 1);
+    corrupted.cap = with_tag(&ep, 9);
+
+    let cap = corrupted.cap;
+    let derived = corrupted.derive_cap(&cap).cap;
+    assert_eq!(derived.cap_type_or_null(), CapTag::CapNullCap);
+    // 损坏的`cap`打断了原始`cap`的子节点
+    assert!(check_invariants(&[original as *const _], |_| {}) > 0);
+
+    // 从损坏的`slot`复制，与从空`slot`复制一样报错
+    let args = [3, 64, 1, 64, 0xf];
+    let err = decode_cnode_invocation(MessageLabel::CNodeCopy, &root, &args, &[root], null_mut())
+        .unwrap_err();
+    assert_eq!(
+        (err._type, err.failedLookupWasSource),
+        (seL4_FailedLookup, 1)
+    );
+
+    assert!(
+        corrupted.delete_all_with(true, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE
+    );
+    assert_eq!(corrupted.cap.try_cap_type(), Err(9));
+    assert!(original.revoke_with(&mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    assert!(
+        original.delete_all_with(true, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE
+    );
+    assert_eq!(original.cap.cap_type_or_null(), CapTag::CapNullCap);
+}
@@ -0,0 +1,414 @@
+//! Decoding and invoking the CNode invocations, in a root CNode of 16 slots addressed with 4 bits.
+#![cfg(feature = "hosted")]
+
+use core::ptr::null_mut;
+use sel4_common::message_info::MessageLabel;
+use sel4_common::sel4_config::{
+    seL4_DeleteFirst, seL4_FailedLookup, seL4_IllegalOperation, seL4_RangeError, seL4_RevokeFirst,
+    seL4_TruncatedMessage,
+};
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, check_invariants, cte_insert, cte_t, decode_cnode_invocation, insert_new_cap,
+    invoke_cnode, syscall_error_t, CapTag, Unbounded,
+};
+
+const DEPTH: usize = 4;
+/// Read and write, without grant and grant reply.
+const READ_WRITE: usize = 0b0011;
+const ALL_RIGHTS: usize = 0b1111;
+
+/// Decode the invocation of `root` with `root` as the extra caps and perform it, return the kernel
+/// it was performed with.
+fn invoke(
+    label: MessageLabel,
+    root: &cap_t,
+    args: &[usize],
+    caller_slot: *mut cte_t,
+) -> Result<HostedKernel, syscall_error_t> {
+    let invocation = decode_cnode_invocation(label, root, args, &[*root, *root], caller_slot)?;
+    let mut kernel = HostedKernel::default();
+    assert_eq!(
+        invoke_cnode(invocation, &mut kernel, &mut Unbounded),
+        exception_t::EXCEPTION_NONE
+    );
+    Ok(kernel)
+}
+
+fn call(
+    label: MessageLabel,
+    root: &cap_t,
+    args: &[usize],
+) -> Result<HostedKernel, syscall_error_t> {
+    invoke(label, root, args, null_mut())
+}
+
+/// A root CNode with an original endpoint in slot 0.
+fn setup(arena: &mut Arena) -> cap_t {
+    let root = arena.new_cnode(4, 0, 0);
+    let slot = cnode_slot(&root, 0);
+    slot.cap = arena.new_endpoint();
+    slot.cteMDBNode.set_revocable(1);
+    root
+}
+
+fn parent(root: &cap_t, index: usize) -> Option<usize> {
+    cnode_slot(root, index).parent().map(|slot| slot.get_ptr())
+}
+
+fn assert_mdb_ok(root: &cap_t, slots: &[usize]) {
+    let slots: Vec<_> = slots
+        .iter()
+        .map(|&i| cnode_slot(root, i) as *const cte_t)
+        .collect();
+    assert_eq!(check_invariants(&slots, |v| panic!("{v:?}")), 0);
+}
+
+#[test]
+fn save_caller() {
+    let mut arena = Arena::new(16);
+    let root = arena.new_cnode(4, 0, 0);
+    let tcb = arena.new_tcb();
+    let caller = cnode_slot(&root, 15);
+    let caller_ptr = caller as *mut cte_t;
+    let save = |dest: usize| {
+        invoke(
+            MessageLabel::CNodeSaveCaller,
+            &root,
+            &[dest, DEPTH],
+            caller_ptr,
+        )
+    };
+
+    // 没有`reply cap`时什么也不做
+    save(1).unwrap();
+    assert_eq!(cnode_slot(&root, 1).cap.get_cap_type(), CapTag::CapNullCap);
+
+    // `reply master`留在原处
+    let master = cap_t::new_reply_cap(1, 1, tcb.get_tcb_ptr());
+    caller.cap = master;
+    save(1).unwrap();
+    assert_eq!(caller.cap.words, master.words);
+    assert_eq!(cnode_slot(&root, 1).cap.get_cap_type(), CapTag::CapNullCap);
+
+    let reply = cap_t::new_reply_cap(1, 0, tcb.get_tcb_ptr());
+    caller.cap = reply;
+    save(1).unwrap();
+    assert_eq!(caller.cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(cnode_slot(&root, 1).cap.words, reply.words);
+
+    caller.cap = reply;
+    assert_eq!(save(1).unwrap_err()._type, seL4_DeleteFirst);
+    assert_eq!(caller.cap.words, reply.words);
+}
+
+#[test]
+fn copy_and_mint() {
+    let mut arena = Arena::new(16);
+    let root = setup(&mut arena);
+    let slot0 = cnode_slot(&root, 0).get_ptr();
+    let ep = cnode_slot(&root, 0).cap;
+
+    call(
+        MessageLabel::CNodeCopy,
+        &root,
+        &[1, DEPTH, 0, DEPTH, READ_WRITE],
+    )
+    .unwrap();
+    let copy = cnode_slot(&root, 1).cap;
+    assert_eq!(copy.get_ep_ptr(), ep.get_ep_ptr());
+    assert_eq!(
+        (
+            copy.get_ep_can_send(),
+            copy.get_ep_can_receive(),
+            copy.get_ep_can_grant(),
+            copy.get_ep_can_grant_reply()
+        ),
+        (1, 1, 0, 0)
+    );
+    assert_eq!(parent(&root, 1), Some(slot0));
+
+    let args = [2, DEPTH, 0, DEPTH, ALL_RIGHTS, 9];
+    call(MessageLabel::CNodeMint, &root, &args).unwrap();
+    assert_eq!(cnode_slot(&root, 2).cap.get_ep_badge(), 9);
+    assert_eq!(parent(&root, 2), Some(slot0));
+    // 已有`badge`的`cap`不能再次加`badge`
+    let args = [3, DEPTH, 2, DEPTH, ALL_RIGHTS, 5];
+    let err = call(MessageLabel::CNodeMint, &root, &args).unwrap_err();
+    assert_eq!(err._type, seL4_IllegalOperation);
+    assert_mdb_ok(&root, &[0]);
+
+    // 目标`slot`不为空，源`slot`为空
+    let err = call(MessageLabel::CNodeCopy, &root, &[1, DEPTH, 0, DEPTH, 0]).unwrap_err();
+    assert_eq!(err._type, seL4_DeleteFirst);
+    let err = call(MessageLabel::CNodeCopy, &root, &[3, DEPTH, 4, DEPTH, 0]).unwrap_err();
+    assert_eq!(
+        (err._type, err.failedLookupWasSource),
+        (seL4_FailedLookup, 1)
+    );
+
+    // 有子节点的`untyped`和`reply cap`
+    let untyped = arena.new_untyped(12);
+    let slot = cnode_slot(&root, 6);
+    slot.cap = untyped;
+    slot.cteMDBNode.set_revocable(1);
+    let child = cap_t::new_endpoint_cap(0, 1, 1, 1, 1, untyped.get_untyped_ptr());
+    insert_new_cap(slot, cnode_slot(&root, 7), &child);
+    let err = call(MessageLabel::CNodeCopy, &root, &[3, DEPTH, 6, DEPTH, 0]).unwrap_err();
+    assert_eq!(err._type, seL4_RevokeFirst);
+    let tcb = arena.new_tcb();
+    cnode_slot(&root, 8).cap = cap_t::new_reply_cap(1, 0, tcb.get_tcb_ptr());
+    let err = call(MessageLabel::CNodeCopy, &root, &[3, DEPTH, 8, DEPTH, 0]).unwrap_err();
+    assert_eq!(err._type, seL4_IllegalOperation);
+    assert_eq!(cnode_slot(&root, 3).cap.get_cap_type(), CapTag::CapNullCap);
+}
+
+#[test]
+fn move_and_mutate() {
+    let mut arena = Arena::new(16);
+    let root = setup(&mut arena);
+    call(
+        MessageLabel::CNodeCopy,
+        &root,
+        &[1, DEPTH, 0, DEPTH, ALL_RIGHTS],
+    )
+    .unwrap();
+    let ep = cnode_slot(&root, 0).cap;
+
+    call(MessageLabel::CNodeMove, &root, &[10, DEPTH, 0, DEPTH]).unwrap();
+    assert_eq!(cnode_slot(&root, 0).cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(cnode_slot(&root, 10).cap.words, ep.words);
+    assert_eq!(cnode_slot(&root, 10).cteMDBNode.get_revocable(), 1);
+    assert_eq!(parent(&root, 1), Some(cnode_slot(&root, 10).get_ptr()));
+    assert_mdb_ok(&root, &[10]);
+
+    // `endpoint`的`badge`不能被`Mutate`改变，和seL4一样得到空`cap`
+    let err = call(MessageLabel::CNodeMutate, &root, &[0, DEPTH, 10, DEPTH, 3]).unwrap_err();
+    assert_eq!(err._type, seL4_IllegalOperation);
+
+    cnode_slot(&root, 3).cap = arena.new_cnode(2, 0, 0);
+    let guard = (0x1 << 6) | 2;
+    call(
+        MessageLabel::CNodeMutate,
+        &root,
+        &[4, DEPTH, 3, DEPTH, guard],
+    )
+    .unwrap();
+    let cnode = cnode_slot(&root, 4).cap;
+    assert_eq!(
+        (cnode.get_cnode_guard(), cnode.get_cnode_guard_size()),
+        (1, 2)
+    );
+    assert_eq!(cnode_slot(&root, 3).cap.get_cap_type(), CapTag::CapNullCap);
+    // `guard`和`radix`加起来超过64位
+    let err = call(MessageLabel::CNodeMutate, &root, &[3, DEPTH, 4, DEPTH, 63]).unwrap_err();
+    assert_eq!(err._type, seL4_IllegalOperation);
+    assert_eq!(cnode_slot(&root, 4).cap.words, cnode.words);
+}
+
+/// Slot 3 holds the CNode cap `a` with a copy in slot 9, slot 4 the CNode cap `b`.
+fn rotate_setup(arena: &mut Arena) -> (cap_t, cap_t, cap_t) {
+    let root = arena.new_cnode(4, 0, 0);
+    let (a, b) = (arena.new_cnode(1, 0, 0), arena.new_cnode(2, 0, 0));
+    let slot = cnode_slot(&root, 3);
+    slot.cap = a;
+    slot.cteMDBNode.set_revocable(1);
+    cte_insert(&a, slot, cnode_slot(&root, 9));
+    let slot = cnode_slot(&root, 4);
+    slot.cap = b;
+    slot.cteMDBNode.set_revocable(1);
+    (root, a, b)
+}
+
+/// `[dest, pivot_data, pivot, src_data, src]` as the message of a Rotate.
+fn rotate_args(dest: usize, pivot: (usize, usize), src: (usize, usize)) -> [usize; 8] {
+    [dest, DEPTH, pivot.1, pivot.0, DEPTH, src.1, src.0, DEPTH]
+}
+
+#[test]
+fn rotate() {
+    let mut arena = Arena::new(16);
+    let (root, a, b) = rotate_setup(&mut arena);
+    let guard = (0x1 << 6) | 1;
+    call(
+        MessageLabel::CNodeRotate,
+        &root,
+        &rotate_args(5, (4, 0), (3, guard)),
+    )
+    .unwrap();
+    assert_eq!(cnode_slot(&root, 3).cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(cnode_slot(&root, 5).cap.words, b.words);
+    let moved = cnode_slot(&root, 4).cap;
+    assert_eq!(moved.get_cnode_ptr(), a.get_cnode_ptr());
+    assert_eq!(
+        (moved.get_cnode_guard(), moved.get_cnode_guard_size()),
+        (1, 1)
+    );
+    assert_eq!(parent(&root, 9), Some(cnode_slot(&root, 4).get_ptr()));
+    assert_eq!(cnode_slot(&root, 4).cteMDBNode.get_revocable(), 1);
+    assert_mdb_ok(&root, &[4, 5, 9]);
+}
+
+#[test]
+fn rotate_into_the_source_slot_swaps() {
+    let mut arena = Arena::new(16);
+    let (root, a, b) = rotate_setup(&mut arena);
+    call(
+        MessageLabel::CNodeRotate,
+        &root,
+        &rotate_args(3, (4, 0), (3, 0)),
+    )
+    .unwrap();
+    assert_eq!(cnode_slot(&root, 3).cap.words, b.words);
+    assert_eq!(cnode_slot(&root, 4).cap.words, a.words);
+    assert_eq!(parent(&root, 9), Some(cnode_slot(&root, 4).get_ptr()));
+    assert_mdb_ok(&root, &[3, 4, 9]);
+}
+
+#[test]
+fn rotate_errors() {
+    let mut arena = Arena::new(16);
+    let (root, a, b) = rotate_setup(&mut arena);
+    let rotate = |args: &[usize]| call(MessageLabel::CNodeRotate, &root, args).unwrap_err();
+    // `pivot`不能是源或者目标
+    for args in [
+        rotate_args(5, (3, 0), (3, 0)),
+        rotate_args(4, (4, 0), (3, 0)),
+    ] {
+        assert_eq!(rotate(&args)._type, seL4_IllegalOperation);
+    }
+    assert_eq!(
+        rotate(&rotate_args(9, (4, 0), (3, 0)))._type,
+        seL4_DeleteFirst
+    );
+    let err = rotate(&rotate_args(5, (4, 0), (6, 0)));
+    assert_eq!(
+        (err._type, err.failedLookupWasSource),
+        (seL4_FailedLookup, 1)
+    );
+    let err = rotate(&rotate_args(5, (6, 0), (3, 0)));
+    assert_eq!(
+        (err._type, err.failedLookupWasSource),
+        (seL4_FailedLookup, 0)
+    );
+    assert_eq!(
+        rotate(&rotate_args(5, (4, 0), (3, 0))[..7])._type,
+        seL4_TruncatedMessage
+    );
+    // `guard`过长时新的`cap`为空
+    assert_eq!(
+        rotate(&rotate_args(5, (4, 63), (3, 0)))._type,
+        seL4_IllegalOperation
+    );
+    assert_eq!(cnode_slot(&root, 3).cap.words, a.words);
+    assert_eq!(cnode_slot(&root, 4).cap.words, b.words);
+    assert_eq!(cnode_slot(&root, 5).cap.get_cap_type(), CapTag::CapNullCap);
+}
+
+#[test]
+fn delete_and_revoke() {
+    let mut arena = Arena::new(16);
+    let root = setup(&mut arena);
+    call(
+        MessageLabel::CNodeCopy,
+        &root,
+        &[1, DEPTH, 0, DEPTH, ALL_RIGHTS],
+    )
+    .unwrap();
+    call(
+        MessageLabel::CNodeCopy,
+        &root,
+        &[2, DEPTH, 1, DEPTH, ALL_RIGHTS],
+    )
+    .unwrap();
+
+    call(MessageLabel::CNodeRevoke, &root, &[0, DEPTH]).unwrap();
+    assert_eq!(
+        cnode_slot(&root, 0).cap.get_cap_type(),
+        CapTag::CapEndpointCap
+    );
+    for i in [1, 2] {
+        assert_eq!(cnode_slot(&root, i).cap.get_cap_type(), CapTag::CapNullCap);
+    }
+    call(MessageLabel::CNodeDelete, &root, &[0, DEPTH]).unwrap();
+    assert_eq!(cnode_slot(&root, 0).cap.get_cap_type(), CapTag::CapNullCap);
+    // 空`slot`上什么也不做
+    call(MessageLabel::CNodeDelete, &root, &[0, DEPTH]).unwrap();
+    call(MessageLabel::CNodeRevoke, &root, &[0, DEPTH]).unwrap();
+
+    // 最后一个`CNode cap`连同其中的`cap`被删除
+    let cnode = arena.new_cnode(2, 0, 0);
+    cnode_slot(&root, 5).cap = cnode;
+    cnode_slot(&cnode, 1).cap = cap_t::new_irq_handler_cap(3);
+    let kernel = call(MessageLabel::CNodeDelete, &root, &[5, DEPTH]).unwrap();
+    assert_eq!(cnode_slot(&root, 5).cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(cnode_slot(&cnode, 1).cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(kernel.deleted.len(), 1);
+    assert_eq!(kernel.deleted[0].get_cap_type(), CapTag::CapIrqHandlerCap);
+}
+
+#[test]
+fn cancel_badged_sends() {
+    let mut arena = Arena::new(16);
+    let root = setup(&mut arena);
+    let ep = cnode_slot(&root, 0).cap;
+    call(
+        MessageLabel::CNodeMint,
+        &root,
+        &[1, DEPTH, 0, DEPTH, ALL_RIGHTS, 9],
+    )
+    .unwrap();
+    call(
+        MessageLabel::CNodeMint,
+        &root,
+        &[2, DEPTH, 0, DEPTH, READ_WRITE, 9],
+    )
+    .unwrap();
+
+    let kernel = call(MessageLabel::CNodeCancelBadgedSends, &root, &[1, DEPTH]).unwrap();
+    assert_eq!(kernel.cancelled_badges, [(ep.get_ep_ptr(), 9)]);
+    let kernel = call(MessageLabel::CNodeCancelBadgedSends, &root, &[0, DEPTH]).unwrap();
+    assert!(kernel.cancelled_badges.is_empty());
+    // 需要全部权限，且必须是`endpoint`
+    cnode_slot(&root, 3).cap = arena.new_notification();
+    for index in [2, 3, 4] {
+        let err = call(MessageLabel::CNodeCancelBadgedSends, &root, &[index, DEPTH]).unwrap_err();
+        assert_eq!(err._type, seL4_IllegalOperation);
+    }
+}
+
+#[test]
+fn decode_errors() {
+    let mut arena = Arena::new(16);
+    let root = setup(&mut arena);
+    let err = call(MessageLabel::UntypedRetype, &root, &[1, DEPTH]).unwrap_err();
+    assert_eq!(err._type, seL4_IllegalOperation);
+    for (label, args) in [
+        (MessageLabel::CNodeDelete, &[1][..]),
+        (MessageLabel::CNodeCopy, &[1, DEPTH, 0, DEPTH]),
+        (MessageLabel::CNodeMint, &[1, DEPTH, 0, DEPTH, ALL_RIGHTS]),
+        (MessageLabel::CNodeMutate, &[1, DEPTH, 0, DEPTH]),
+        (MessageLabel::CNodeMove, &[1, DEPTH, 0]),
+    ] {
+        assert_eq!(
+            call(label, &root, args).unwrap_err()._type,
+            seL4_TruncatedMessage
+        );
+    }
+    let args = [1, DEPTH, 0, DEPTH];
+    let err = decode_cnode_invocation(MessageLabel::CNodeMove, &root, &args, &[], null_mut())
+        .unwrap_err();
+    assert_eq!(err._type, seL4_TruncatedMessage);
+    let err = call(MessageLabel::CNodeDelete, &root, &[1, 0]).unwrap_err();
+    assert_eq!(
+        (err._type, err.rangeErrorMin, err.rangeErrorMax),
+        (seL4_RangeError, 1, 64)
+    );
+    // 目标查找失败时`failedLookupWasSource`为0
+    let err = call(MessageLabel::CNodeDelete, &root, &[1, 8]).unwrap_err();
+    assert_eq!(
+        (err._type, err.failedLookupWasSource),
+        (seL4_FailedLookup, 0)
+    );
+}
@@ -0,0 +1,133 @@
+//! `CSpaceContinuation` resumed after preemption, and abandoned when its slot changes.
+#![cfg(feature = "hosted")]
+
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, cte_insert, CSpaceContinuation, CapTag, FixedBudget, Unbounded,
+};
+
+/// Slot 0 holds an original endpoint with `copies` copies in the following slots.
+fn endpoint_copies(arena: &mut Arena, copies: usize) -> cap_t {
+    let root = arena.new_cnode(6, 0, 0);
+    let slot = cnode_slot(&root, 0);
+    slot.cap = arena.new_endpoint();
+    slot.cteMDBNode.set_revocable(1);
+    let cap = slot.cap;
+    for i in 1..=copies {
+        cte_insert(&cap, cnode_slot(&root, 0), cnode_slot(&root, i));
+    }
+    root
+}
+
+#[test]
+fn revoke_resumed_to_completion() {
+    let mut arena = Arena::new(16);
+    let mut kernel = HostedKernel::default();
+    let root = endpoint_copies(&mut arena, 40);
+    let slot = cnode_slot(&root, 0);
+    let mut op = CSpaceContinuation::revoke(slot);
+    let mut budget = FixedBudget::new(3);
+    while op.resume(&mut kernel, &mut budget) == exception_t::EXCEPTION_PREEMTED {
+        // 每次运行删除3个子节点，从上次停下的位置继续
+        assert_eq!(op.deleted(), 3 * op.runs());
+        let next = slot.cteMDBNode.get_next();
+        assert_eq!(op.stopped_at(), Some(next as *mut _));
+        assert_eq!(
+            cnode_slot(&root, 0).cap.get_cap_type(),
+            CapTag::CapEndpointCap
+        );
+        budget.refill(3);
+    }
+    assert!(op.is_done() && !op.is_abandoned());
+    assert_eq!(op.stopped_at(), None);
+    assert_eq!(op.runs(), 14);
+    assert_eq!((op.deleted(), op.work_units()), (40, 40));
+    assert_eq!(slot.cteMDBNode.get_next(), 0);
+    for i in 1..=40 {
+        assert_eq!(cnode_slot(&root, i).cap.get_cap_type(), CapTag::CapNullCap);
+    }
+    assert_eq!(
+        op.resume(&mut kernel, &mut budget),
+        exception_t::EXCEPTION_NONE
+    );
+    assert_eq!(op.runs(), 14);
+}
+
+#[test]
+fn delete_resumed_to_completion() {
+    let mut arena = Arena::new(16);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(2, 0, 0);
+    let cnode = arena.new_cnode(3, 0, 0);
+    for i in 0..8 {
+        cnode_slot(&cnode, i).cap = arena.new_endpoint();
+    }
+    let slot = cnode_slot(&root, 0);
+    slot.cap = cnode;
+    slot.cteMDBNode.set_revocable(1);
+
+    let mut op = CSpaceContinuation::delete(slot, true);
+    let mut budget = FixedBudget::new(2);
+    while op.resume(&mut kernel, &mut budget) == exception_t::EXCEPTION_PREEMTED {
+        assert_eq!(op.stopped_at(), Some(slot as *mut _));
+        assert_eq!(slot.cap.get_cap_type(), CapTag::CapZombieCap);
+        budget.refill(2);
+    }
+    assert!(op.runs() > 1);
+    // 8个`endpoint`和`CNode`自己
+    assert_eq!(op.deleted(), 9);
+    assert!(op.work_units() >= 8);
+    assert_eq!(slot.cap.get_cap_type(), CapTag::CapNullCap);
+}
+
+#[test]
+fn changed_slot_is_abandoned() {
+    let mut arena = Arena::new(16);
+    let mut kernel = HostedKernel::default();
+    let root = endpoint_copies(&mut arena, 4);
+    let slot = cnode_slot(&root, 0);
+    let mut op = CSpaceContinuation::revoke(slot);
+    let status = op.resume(&mut kernel, &mut FixedBudget::new(1));
+    assert_eq!(status, exception_t::EXCEPTION_PREEMTED);
+    assert_eq!(op.deleted(), 1);
+
+    // 另一个操作删除了`slot`中的`cap`，并放入新的`cap`
+    assert_eq!(
+        slot.delete_all_with(true, &mut kernel, &mut Unbounded),
+        exception_t::EXCEPTION_NONE
+    );
+    slot.cap = arena.new_endpoint();
+    let status = op.resume(&mut kernel, &mut Unbounded);
+    assert_eq!(status, exception_t::EXCEPTION_SYSCALL_ERROR);
+    assert!(op.is_done() && op.is_abandoned());
+    assert_eq!((op.runs(), op.deleted(), op.stopped_at()), (1, 1, None));
+    // 剩下的副本没有被删除
+    let left = (1..=4)
+        .filter(|&i| cnode_slot(&root, i).cap.get_cap_type() == CapTag::CapEndpointCap)
+        .count();
+    assert_eq!(left, 3);
+    assert_eq!(
+        op.resume(&mut kernel, &mut Unbounded),
+        exception_t::EXCEPTION_NONE
+    );
+
+    // 删除被抢占后，`zombie`被换成了别的`cap`
+    let cnode = arena.new_cnode(3, 0, 0);
+    for i in 0..8 {
+        cnode_slot(&cnode, i).cap = arena.new_endpoint();
+    }
+    let slot = cnode_slot(&root, 10);
+    slot.cap = cnode;
+    let mut op = CSpaceContinuation::delete(slot, true);
+    let status = op.resume(&mut kernel, &mut FixedBudget::new(1));
+    assert_eq!(status, exception_t::EXCEPTION_PREEMTED);
+    let mut zombie = slot.cap;
+    zombie
+        .set_zombie_number(zombie.get_zombie_number().unwrap() - 1)
+        .unwrap();
+    slot.cap = zombie;
+    let status = op.resume(&mut kernel, &mut Unbounded);
+    assert_eq!(status, exception_t::EXCEPTION_SYSCALL_ERROR);
+    assert!(op.is_abandoned());
+}
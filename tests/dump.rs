@@ -0,0 +1,159 @@
+//! The text and DOT output of the derivation tree dump.
+#![cfg(feature = "hosted")]
+
+use sel4_cspace::hosted::{cnode_slot, Arena};
+use sel4_cspace::interface::{
+    cap_t, cte_insert, insert_new_cap, write_cnode_mdb_dot, write_cnode_mdb_tree, write_mdb_dot,
+    write_mdb_tree, MAX_TREE_DEPTH,
+};
+
+/// Slot 0 holds an original endpoint, slot 1 a copy minted with badge 7 and slot 2 a copy of that.
+/// Slot 3 holds a CNode whose slot 0 has an unbadged copy of the endpoint, slot 4 a notification.
+fn cspace(arena: &mut Arena) -> cap_t {
+    let root = arena.new_cnode(3, 0, 0);
+    let ep = cnode_slot(&root, 0);
+    ep.cap = arena.new_endpoint();
+    ep.cteMDBNode.set_revocable(1);
+    let mut badged = ep.cap;
+    badged.set_ep_badge(7);
+    cte_insert(&badged, ep, cnode_slot(&root, 1));
+    cte_insert(&badged, cnode_slot(&root, 1), cnode_slot(&root, 2));
+
+    let inner = cnode_slot(&root, 3);
+    inner.cap = arena.new_cnode(1, 0, 0);
+    inner.cteMDBNode.set_revocable(1);
+    let ep_cap = ep.cap;
+    cte_insert(&ep_cap, ep, cnode_slot(&inner.cap, 0));
+
+    let ntfn = cnode_slot(&root, 4);
+    ntfn.cap = arena.new_notification();
+    ntfn.cteMDBNode.set_revocable(1);
+    root
+}
+
+fn addr(root: &cap_t, index: usize) -> usize {
+    cnode_slot(root, index).get_ptr()
+}
+
+#[test]
+fn text_tree() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 3).cap;
+    let ep = cnode_slot(&root, 0).cap.get_cap_ptr();
+    let expected = format!(
+        "{:#x} CapEndpointCap ptr={ep:#x} badge=0x0 [R-]\n  \
+         {:#x} CapEndpointCap ptr={ep:#x} badge=0x0 [--]\n  \
+         {:#x} CapEndpointCap ptr={ep:#x} badge=0x7 [RF]\n    \
+         {:#x} CapEndpointCap ptr={ep:#x} badge=0x7 [--]\n",
+        addr(&root, 0),
+        addr(&inner, 0),
+        addr(&root, 1),
+        addr(&root, 2),
+    );
+    let mut out = String::new();
+    write_mdb_tree(&mut out, cnode_slot(&root, 0)).unwrap();
+    assert_eq!(out, expected);
+
+    // 嵌套`CNode`中的副本属于同一棵树，只输出一次
+    let expected = format!(
+        "{expected}{:#x} CapCNodeCap ptr={:#x} [R-]\n\
+         {:#x} CapNotificationCap ptr={:#x} badge=0x0 [R-]\n",
+        addr(&root, 3),
+        inner.get_cap_ptr(),
+        addr(&root, 4),
+        cnode_slot(&root, 4).cap.get_cap_ptr(),
+    );
+    let mdb = cnode_slot(&root, 0).cteMDBNode;
+    let mut out = String::new();
+    write_cnode_mdb_tree(&mut out, &root).unwrap();
+    assert_eq!(out, expected);
+    assert_eq!(cnode_slot(&root, 0).cteMDBNode, mdb);
+}
+
+#[test]
+fn dot_graph() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 3).cap;
+    let ep = cnode_slot(&root, 0).cap.get_cap_ptr();
+    let (s0, n0, s1, s2) = (
+        addr(&root, 0),
+        addr(&inner, 0),
+        addr(&root, 1),
+        addr(&root, 2),
+    );
+    let expected = format!(
+        "digraph mdb {{\n    node [shape=box];\n    \
+         n{s0:x} [label=\"{s0:#x} CapEndpointCap ptr={ep:#x} badge=0x0 [R-]\"];\n    \
+         n{n0:x} [label=\"{n0:#x} CapEndpointCap ptr={ep:#x} badge=0x0 [--]\"];\n    \
+         n{s0:x} -> n{n0:x};\n    \
+         n{s1:x} [label=\"{s1:#x} CapEndpointCap ptr={ep:#x} badge=0x7 [RF]\"];\n    \
+         n{s0:x} -> n{s1:x};\n    \
+         n{s2:x} [label=\"{s2:#x} CapEndpointCap ptr={ep:#x} badge=0x7 [--]\"];\n    \
+         n{s1:x} -> n{s2:x};\n}}\n"
+    );
+    let mut out = String::new();
+    write_mdb_dot(&mut out, cnode_slot(&root, 0)).unwrap();
+    assert_eq!(out, expected);
+
+    let mut out = String::new();
+    write_cnode_mdb_dot(&mut out, &root).unwrap();
+    assert!(out.starts_with(&expected[..expected.len() - 2]));
+    assert_eq!(out.matches("[label=").count(), 6);
+    assert!(out.ends_with("[R-]\"];\n}\n"));
+}
+
+#[test]
+fn not_a_cnode() {
+    let mut arena = Arena::new(16);
+    let ep = arena.new_endpoint();
+    let mut out = String::new();
+    assert!(write_cnode_mdb_tree(&mut out, &ep).is_err());
+    assert!(write_cnode_mdb_dot(&mut out, &ep).is_err());
+}
+
+/// A chain of revocable caps to one endpoint, each the parent of all the following ones.
+#[test]
+fn deep_tree_is_truncated() {
+    const LEN: usize = MAX_TREE_DEPTH + 6;
+    let mut arena = Arena::new(16);
+    let root = arena.new_cnode(7, 0, 0);
+    let ep = arena.new_endpoint();
+    cnode_slot(&root, 0).cap = ep;
+    cnode_slot(&root, 0).cteMDBNode.set_revocable(1);
+    for i in 1..LEN {
+        insert_new_cap(cnode_slot(&root, i - 1), cnode_slot(&root, i), &ep);
+    }
+
+    let mut out = String::new();
+    write_mdb_tree(&mut out, cnode_slot(&root, 0)).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), MAX_TREE_DEPTH + 1);
+    for (depth, line) in lines[..MAX_TREE_DEPTH].iter().enumerate() {
+        let label = format!("{:#x} ", addr(&root, depth));
+        assert!(
+            line.starts_with(&format!("{}{label}", "  ".repeat(depth))),
+            "{line}"
+        );
+    }
+    assert_eq!(
+        lines[MAX_TREE_DEPTH],
+        format!("... 6 slots at depth {MAX_TREE_DEPTH} or deeper not shown")
+    );
+
+    let mut out = String::new();
+    write_mdb_dot(&mut out, cnode_slot(&root, 0)).unwrap();
+    assert_eq!(out.matches("[label=").count(), MAX_TREE_DEPTH);
+    assert_eq!(out.matches(" -> ").count(), MAX_TREE_DEPTH - 1);
+    // 最深的一条边仍来自真正的父节点
+    let (parent, last) = (
+        addr(&root, MAX_TREE_DEPTH - 2),
+        addr(&root, MAX_TREE_DEPTH - 1),
+    );
+    assert!(out.contains(&format!("n{parent:x} -> n{last:x};\n")));
+    assert!(out.ends_with(&format!(
+        "    // 6 slots under n{:x} at depth {MAX_TREE_DEPTH} or deeper not shown\n}}\n",
+        addr(&root, 0)
+    )));
+}
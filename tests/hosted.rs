@@ -0,0 +1,242 @@
+//! The hosted arena and `finalise_cap`, and the cspace operations run on them.
+#![cfg(feature = "hosted")]
+
+use sel4_common::sel4_config::{seL4_SlotBits, seL4_TCBBits, tcbCNodeEntries, tcbCTable};
+use sel4_common::structures::exception_t;
+use sel4_common::{BIT, MASK};
+use sel4_cspace::hosted::{cnode_slot, finalise_cap, tcb_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, check_invariants, cte_insert, cte_t, insert_new_cap, CapTag, Unbounded, ZombieCap,
+    ZombieKind,
+};
+
+fn zombie(cap: &cap_t) -> ZombieCap {
+    ZombieCap::try_from(*cap).unwrap()
+}
+
+#[test]
+fn arena_objects_are_aligned_and_zeroed() {
+    let mut arena = Arena::new(20);
+    let mut last = 0;
+    for bits in [4, 12, 5, 10, 16, 6] {
+        let ptr = arena.alloc(bits);
+        assert_eq!(ptr & MASK!(bits), 0);
+        assert!(ptr >= last && ptr + BIT!(bits) <= BIT!(38));
+        let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, BIT!(bits)) };
+        assert!(bytes.iter().all(|b| *b == 0));
+        last = ptr + BIT!(bits);
+    }
+    let cnode = arena.new_cnode(3, 4, 0b1010);
+    assert_eq!(cnode.get_cnode_ptr() & MASK!(3 + seL4_SlotBits), 0);
+    assert_eq!(
+        (cnode.get_cnode_guard_size(), cnode.get_cnode_guard()),
+        (4, 0b1010)
+    );
+    assert_eq!(
+        cnode_slot(&cnode, 7).get_ptr(),
+        cnode.get_cnode_ptr() + (7 << seL4_SlotBits)
+    );
+    let tcb = arena.new_tcb();
+    let base = tcb.get_tcb_ptr() & !MASK!(seL4_TCBBits);
+    assert_eq!(tcb_slot(&tcb, tcbCTable).get_ptr(), base);
+    let untyped = arena.new_untyped(12);
+    assert_eq!(untyped.get_untyped_ptr() & MASK!(12), 0);
+    assert_eq!(untyped.get_untyped_free_index(), 0);
+}
+
+#[test]
+fn arena_restore() {
+    let mut arena = Arena::new(16);
+    let cnode = arena.new_cnode(2, 0, 0);
+    let snapshot = arena.snapshot();
+    let ep = arena.new_endpoint();
+    cnode_slot(&cnode, 1).cap = ep;
+    arena.restore(&snapshot);
+    assert_eq!(cnode_slot(&cnode, 1).cap.get_cap_type(), CapTag::CapNullCap);
+    // 快照之后分配的对象被释放，重新分配到同一地址
+    assert_eq!(arena.new_endpoint().get_ep_ptr(), ep.get_ep_ptr());
+}
+
+#[test]
+fn finalise_cap_builds_zombies() {
+    let mut arena = Arena::new(20);
+    let cnode = arena.new_cnode(4, 0, 0);
+    let ret = finalise_cap(&cnode, true, false);
+    let z = zombie(&ret.remainder);
+    assert_eq!(
+        (z.kind(), z.ptr(), z.remaining_slots()),
+        (ZombieKind::CNode { radix: 4 }, cnode.get_cnode_ptr(), 16)
+    );
+    assert_eq!(ret.cleanupInfo.get_cap_type(), CapTag::CapNullCap);
+    // 不是最后一个`cap`时不需要清理
+    let ret = finalise_cap(&cnode, false, false);
+    assert_eq!(ret.remainder.get_cap_type(), CapTag::CapNullCap);
+
+    let tcb = arena.new_tcb();
+    let z = zombie(&finalise_cap(&tcb, true, false).remainder);
+    assert_eq!(
+        (z.kind(), z.ptr(), z.remaining_slots()),
+        (
+            ZombieKind::Tcb,
+            tcb_slot(&tcb, 0).get_ptr(),
+            tcbCNodeEntries
+        )
+    );
+
+    let cap = z.into();
+    assert_eq!(finalise_cap(&cap, true, false).remainder.words, cap.words);
+    let handler = cap_t::new_irq_handler_cap(3);
+    let ret = finalise_cap(&handler, true, false);
+    assert_eq!(
+        (ret.remainder.get_cap_type(), ret.cleanupInfo.words),
+        (CapTag::CapNullCap, handler.words)
+    );
+    let ep = arena.new_endpoint();
+    let ret = finalise_cap(&ep, true, true);
+    assert_eq!(ret.remainder.get_cap_type(), CapTag::CapNullCap);
+}
+
+#[test]
+fn cte_insert_links_slots() {
+    let mut arena = Arena::new(16);
+    let root = arena.new_cnode(3, 0, 0);
+    let parent = cnode_slot(&root, 0);
+    parent.cap = arena.new_untyped(12);
+    parent.cteMDBNode.set_revocable(1);
+    let ut = parent.cap;
+    let ep = cap_t::new_endpoint_cap(0, 1, 1, 1, 1, ut.get_untyped_ptr());
+    insert_new_cap(parent, cnode_slot(&root, 2), &ep);
+    cte_insert(&ep, cnode_slot(&root, 2), cnode_slot(&root, 5));
+    cte_insert(&ut, parent, cnode_slot(&root, 1));
+
+    let ptr = |i| cnode_slot(&root, i).get_ptr();
+    // 链表顺序为 0, 1, 2, 5
+    let order: Vec<_> = parent.mdb_iter().map(|cte| cte.get_ptr()).collect();
+    assert_eq!(order, [ptr(0), ptr(1), ptr(2), ptr(5)]);
+    for (i, prev, next) in [(0, 0, ptr(1)), (1, ptr(0), ptr(2)), (5, ptr(2), 0)] {
+        let node = cnode_slot(&root, i).cteMDBNode;
+        assert_eq!((node.get_prev(), node.get_next()), (prev, next));
+    }
+    // `untyped cap`的副本总是可撤销，`badge`不变的`endpoint cap`副本不可撤销
+    let revocable = [0, 1, 2, 5].map(|i| cnode_slot(&root, i).cteMDBNode.get_revocable());
+    assert_eq!(revocable, [1, 1, 1, 0]);
+    assert!(parent.is_mdb_parent_of(cnode_slot(&root, 1)));
+    assert!(cnode_slot(&root, 1).is_mdb_parent_of(cnode_slot(&root, 2)));
+    // 未加`badge`的原始`endpoint cap`是它所有副本的父节点
+    assert!(cnode_slot(&root, 2).is_mdb_parent_of(cnode_slot(&root, 5)));
+    assert_eq!(check_invariants(&[parent as *const cte_t], |_| {}), 0);
+}
+
+#[test]
+fn revoke_deletes_descendants() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(4, 0, 0);
+    let parent = cnode_slot(&root, 0);
+    parent.cap = arena.new_untyped(16);
+    parent.cteMDBNode.set_revocable(1);
+    let ut = parent.cap;
+    let other = cnode_slot(&root, 1);
+    other.cap = arena.new_endpoint();
+    other.cteMDBNode.set_revocable(1);
+    let ep = other.cap;
+    cte_insert(&ep, other, cnode_slot(&root, 15));
+    for i in 2..6 {
+        let child = cap_t::new_untyped_cap(0, 0, 12, ut.get_untyped_ptr() + (i << 12));
+        insert_new_cap(parent, cnode_slot(&root, i), &child);
+        insert_new_cap(
+            cnode_slot(&root, i),
+            cnode_slot(&root, i + 8),
+            &cap_t::new_endpoint_cap(0, 1, 1, 1, 1, child.get_untyped_ptr()),
+        );
+    }
+    assert_eq!(parent.descendants().count(), 8);
+
+    assert!(parent.revoke_with(&mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    assert_eq!(parent.descendants().count(), 0);
+    for i in (2..6).chain(10..14) {
+        assert_eq!(cnode_slot(&root, i).cap.get_cap_type(), CapTag::CapNullCap);
+    }
+    assert_eq!(parent.cteMDBNode.get_next(), 0);
+    // 其它对象的`cap`不受影响
+    assert_eq!(cnode_slot(&root, 15).cteMDBNode.get_prev(), other.get_ptr());
+    assert!(kernel.deleted.is_empty());
+    assert_eq!(
+        check_invariants(&[parent as *const cte_t, other], |_| {}),
+        0
+    );
+}
+
+#[test]
+fn delete_all_nested_cnodes() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(2, 0, 0);
+    let outer = arena.new_cnode(3, 0, 0);
+    let inner = arena.new_cnode(2, 0, 0);
+    let tcb = arena.new_tcb();
+    let slot = cnode_slot(&root, 0);
+    slot.cap = outer;
+    cnode_slot(&outer, 7).cap = inner;
+    cnode_slot(&outer, 2).cap = tcb;
+    cnode_slot(&outer, 4).cap = cap_t::new_irq_handler_cap(9);
+    cnode_slot(&inner, 3).cap = arena.new_endpoint();
+    tcb_slot(&tcb, tcbCTable).cap = arena.new_notification();
+
+    assert!(slot.delete_all_with(true, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    assert_eq!(slot.cap.get_cap_type(), CapTag::CapNullCap);
+    for i in 0..8 {
+        assert_eq!(cnode_slot(&outer, i).cap.get_cap_type(), CapTag::CapNullCap);
+    }
+    // 内层的`CNode`和`TCB`不是立即删除，各自留下指向自身的`zombie`
+    let z = zombie(&cnode_slot(&inner, 0).cap);
+    assert_eq!(z.ptr(), cnode_slot(&inner, 0).get_ptr());
+    let z = zombie(&tcb_slot(&tcb, tcbCTable).cap);
+    assert_eq!(
+        (z.kind(), z.ptr()),
+        (ZombieKind::Tcb, tcb_slot(&tcb, 0).get_ptr())
+    );
+    assert_eq!(
+        cnode_slot(&inner, 3).cap.get_cap_type(),
+        CapTag::CapEndpointCap
+    );
+    // `irq handler`的清理信息交给`post_cap_deletion`
+    let deleted: Vec<_> = kernel.deleted.iter().map(|cap| cap.words).collect();
+    assert_eq!(deleted, [cap_t::new_irq_handler_cap(9).words]);
+}
+
+#[test]
+fn cyclic_zombie() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(2, 0, 0);
+    let cnode = arena.new_cnode(2, 0, 0);
+    cnode_slot(&root, 1).cap = cnode;
+    cnode_slot(&cnode, 0).cap = arena.new_endpoint();
+    cnode_slot(&cnode, 3).cap = arena.new_notification();
+
+    // 非立即删除把`zombie`换入`CNode`自己的第一个`slot`
+    let slot = cnode_slot(&root, 1);
+    assert!(
+        slot.delete_all_with(false, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE
+    );
+    assert_eq!(slot.cap.get_cap_type(), CapTag::CapNullCap);
+    let first = cnode_slot(&cnode, 0);
+    let z = zombie(&first.cap);
+    assert_eq!(
+        (z.kind(), z.ptr(), z.remaining_slots()),
+        (ZombieKind::CNode { radix: 2 }, first.get_ptr(), 4)
+    );
+    assert_eq!(
+        cnode_slot(&cnode, 3).cap.get_cap_type(),
+        CapTag::CapNotificationCap
+    );
+
+    // 立即删除循环`zombie`会删除`CNode`剩下的`slot`，最后删除它自己
+    assert!(
+        first.delete_all_with(true, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE
+    );
+    for i in 0..4 {
+        assert_eq!(cnode_slot(&cnode, i).cap.get_cap_type(), CapTag::CapNullCap);
+    }
+}
@@ -0,0 +1,360 @@
+//! The indexes of the optional features against a scan of every slot, after each cte operation.
+#![cfg(all(
+    feature = "hosted",
+    any(feature = "region_index", feature = "object_index")
+))]
+
+use sel4_common::message_info::MessageLabel;
+use sel4_common::object::ObjectType;
+use sel4_common::structures::exception_t;
+use sel4_common::BIT;
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, cte_insert, cte_move, cte_swap, cte_t, decode_untyped_invocation, invoke_untyped_retype,
+    CSpaceWalker, Unbounded,
+};
+
+/// Every non-null slot reachable from `root`, once even if several caps name its `CNode`.
+fn scan(root: &cap_t) -> Vec<&'static cte_t> {
+    let mut slots: Vec<_> = CSpaceWalker::new(root).map(|(_, _, slot)| slot).collect();
+    slots.sort_by_key(|slot| slot.get_ptr());
+    slots.dedup_by_key(|slot| slot.get_ptr());
+    slots
+}
+
+fn addresses<'a>(slots: impl IntoIterator<Item = &'a cte_t>) -> Vec<usize> {
+    slots.into_iter().map(|slot| slot.get_ptr()).collect()
+}
+
+#[cfg(feature = "region_index")]
+mod region {
+    use super::*;
+    use sel4_cspace::interface::{caps_overlapping, enclosing_untyped, CapTag};
+
+    fn is_indexed(slot: &cte_t) -> bool {
+        matches!(
+            slot.cap.get_cap_type(),
+            CapTag::CapUntypedCap | CapTag::CapFrameCap
+        )
+    }
+
+    fn overlapping(slots: &[&'static cte_t], start: usize, end: usize) -> Vec<usize> {
+        let mut found: Vec<_> = slots
+            .iter()
+            .filter(|slot| is_indexed(slot))
+            .filter(|slot| {
+                let region = slot.cap.object_region().unwrap();
+                start < end && region.base < end && start <= region.top()
+            })
+            .map(|slot| {
+                let region = slot.cap.object_region().unwrap();
+                let frame = slot.cap.get_cap_type() == CapTag::CapFrameCap;
+                (frame, region.size_bits, region.base, slot.get_ptr())
+            })
+            .collect();
+        found.sort();
+        found.into_iter().map(|(_, _, _, slot)| slot).collect()
+    }
+
+    fn enclosing(slots: &[&'static cte_t], start: usize, end: usize) -> Option<usize> {
+        slots
+            .iter()
+            .filter(|slot| slot.cap.get_cap_type() == CapTag::CapUntypedCap && start < end)
+            .filter(|slot| {
+                let region = slot.cap.object_region().unwrap();
+                region.base <= start && end - 1 <= region.top()
+            })
+            .map(|slot| (slot.cap.object_region().unwrap().size_bits, slot.get_ptr()))
+            .min()
+            .map(|(_, slot)| slot)
+    }
+
+    /// Compare the index with the scan for the region of every cap and for `ranges`.
+    pub fn check(root: &cap_t, ranges: &[(usize, usize)]) {
+        let slots = scan(root);
+        let regions = slots
+            .iter()
+            .filter_map(|slot| slot.cap.object_region())
+            .map(|region| (region.base, region.top() + 1));
+        for (start, end) in regions.chain(ranges.iter().copied()) {
+            assert_eq!(
+                addresses(caps_overlapping(start, end)),
+                overlapping(&slots, start, end),
+                "caps_overlapping({start:#x}, {end:#x})"
+            );
+            assert_eq!(
+                enclosing_untyped(start, end).map(|slot| slot.get_ptr()),
+                enclosing(&slots, start, end),
+                "enclosing_untyped({start:#x}, {end:#x})"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "object_index")]
+mod object {
+    use super::*;
+    use sel4_cspace::interface::{slots_of_object_at, slots_referencing, ObjectKey};
+
+    fn referencing(slots: &[&'static cte_t], key: Option<ObjectKey>) -> Vec<usize> {
+        let slots = slots
+            .iter()
+            .filter(|slot| key.is_some() && ObjectKey::of_cap(&slot.cap) == key);
+        addresses(slots.copied())
+    }
+
+    fn sorted(mut slots: Vec<usize>) -> Vec<usize> {
+        slots.sort();
+        slots
+    }
+
+    /// Compare the index and `object_slots` with the scan for every cap, and the index for every
+    /// object in `kernel.created`.
+    pub fn check(root: &cap_t, kernel: &HostedKernel) {
+        let slots = scan(root);
+        for slot in &slots {
+            let key = ObjectKey::of_cap(&slot.cap);
+            let expected = referencing(&slots, key);
+            assert_eq!(addresses(slots_referencing(&slot.cap)), expected, "{key:?}");
+            // 场景中没有`reply cap`和`untyped`的副本，每个对象的`cap`在`MDB`中连续
+            assert_eq!(sorted(addresses(slot.object_slots())), expected, "{key:?}");
+        }
+        for &(object_type, ptr) in &kernel.created {
+            let user_size = match object_type {
+                ObjectType::UnytpedObject => 16,
+                ObjectType::CapTableObject => 2,
+                _ => 0,
+            };
+            let key = ObjectKey::of_object(object_type, ptr, user_size);
+            assert_eq!(
+                addresses(slots_of_object_at(object_type, ptr, user_size)),
+                referencing(&slots, Some(key)),
+                "{key:?}"
+            );
+        }
+    }
+}
+
+/// Check every index enabled against a scan of the CSpace.
+fn check(root: &cap_t, untyped: &cap_t, kernel: &HostedKernel) {
+    let base = untyped.get_untyped_ptr();
+    let size = BIT!(untyped.get_untyped_block_size());
+    let ranges = [
+        (base, base + size),
+        (base + 0x1800, base + 0x2800),
+        (base + 0x10000 - 1, base + 0x10000 + 1),
+        (base + 0x3000, base + 0x3000),
+        (base + size - 1, base + size),
+    ];
+    #[cfg(feature = "region_index")]
+    region::check(root, &ranges);
+    #[cfg(feature = "object_index")]
+    object::check(root, kernel);
+    let _ = (ranges, kernel);
+}
+
+fn retype(
+    kernel: &mut HostedKernel,
+    slot: &mut cte_t,
+    root: &cap_t,
+    object_type: ObjectType,
+    user_size: usize,
+    offset: usize,
+    window: usize,
+) {
+    let args = [object_type as usize, user_size, 0, 0, offset, window];
+    let r = decode_untyped_invocation(MessageLabel::UntypedRetype, slot, &args, &[*root]).unwrap();
+    assert!(invoke_untyped_retype(&r, kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+}
+
+#[test]
+fn index_matches_scan() {
+    let mut arena = Arena::new(24);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(5, 0, 0);
+    let slot = |i| cnode_slot(&root, i);
+    // 直接写入`slot`的`cap`不在索引中，通过`cte_move`放入`root`
+    let holder = arena.new_cnode(0, 0, 0);
+    let untyped = arena.new_untyped(22);
+    cnode_slot(&holder, 0).cap = untyped;
+    cnode_slot(&holder, 0).cteMDBNode.set_revocable(1);
+    cte_move(&untyped, cnode_slot(&holder, 0), slot(0));
+    check(&root, &untyped, &kernel);
+
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::UnytpedObject,
+        16,
+        1,
+        2,
+    );
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::NormalPageObject,
+        0,
+        3,
+        3,
+    );
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::EndpointObject,
+        0,
+        7,
+        2,
+    );
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::CapTableObject,
+        2,
+        9,
+        1,
+    );
+    // `MegaPage`按2M对齐，放在最后
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::MegaPageObject,
+        0,
+        6,
+        1,
+    );
+    retype(
+        &mut kernel,
+        slot(1),
+        &root,
+        ObjectType::NormalPageObject,
+        0,
+        10,
+        2,
+    );
+    check(&root, &untyped, &kernel);
+
+    // 复制，包括加`badge`的复制和复制到嵌套的`CNode`中
+    let copy = |src: usize, dest: &mut cte_t| {
+        let cap = slot(src).cap;
+        cte_insert(&cap, slot(src), dest);
+    };
+    copy(3, slot(12));
+    copy(10, slot(13));
+    copy(7, slot(14));
+    let mut badged = slot(7).cap;
+    badged.set_ep_badge(5);
+    cte_insert(&badged, slot(7), slot(15));
+    copy(15, slot(16));
+    copy(9, slot(17));
+    copy(5, cnode_slot(&slot(9).cap, 1));
+    check(&root, &untyped, &kernel);
+
+    let cap = slot(12).cap;
+    cte_move(&cap, slot(12), slot(20));
+    let cap = slot(15).cap;
+    cte_move(&cap, slot(15), slot(21));
+    check(&root, &untyped, &kernel);
+
+    let (cap1, cap2) = (slot(13).cap, slot(4).cap);
+    cte_swap(&cap1, slot(13), &cap2, slot(4));
+    let (cap1, cap2) = (slot(14).cap, slot(21).cap);
+    cte_swap(&cap1, slot(14), &cap2, slot(21));
+    check(&root, &untyped, &kernel);
+
+    // 最后一个`CNode cap`被删除时，其中的`cap`也被删除
+    for i in [20, 16, 9, 17] {
+        let status = slot(i).delete_all_with(true, &mut kernel, &mut Unbounded);
+        assert!(status == exception_t::EXCEPTION_NONE);
+    }
+    check(&root, &untyped, &kernel);
+
+    assert!(slot(1).revoke_with(&mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    check(&root, &untyped, &kernel);
+    assert!(slot(0).revoke_with(&mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    check(&root, &untyped, &kernel);
+    assert_eq!(addresses(scan(&root)), [slot(0).get_ptr()]);
+}
+
+/// `Arena::restore` puts back the index entries of the snapshot, dropping the arena drops them.
+#[test]
+fn index_follows_arena() {
+    let mut arena = Arena::new(24);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(4, 0, 0);
+    let slot = |i| cnode_slot(&root, i);
+    let holder = arena.new_cnode(0, 0, 0);
+    let untyped = arena.new_untyped(22);
+    cnode_slot(&holder, 0).cap = untyped;
+    cnode_slot(&holder, 0).cteMDBNode.set_revocable(1);
+    cte_move(&untyped, cnode_slot(&holder, 0), slot(0));
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::NormalPageObject,
+        0,
+        1,
+        2,
+    );
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::EndpointObject,
+        0,
+        3,
+        1,
+    );
+    let snapshot = arena.snapshot();
+    check(&root, &untyped, &kernel);
+
+    // 快照之后的移动、删除和新建的对象在恢复后都不应留在索引中
+    let cap = slot(1).cap;
+    cte_move(&cap, slot(1), slot(10));
+    assert!(
+        slot(3).delete_all_with(true, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE
+    );
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::NormalPageObject,
+        0,
+        4,
+        3,
+    );
+    retype(
+        &mut kernel,
+        slot(0),
+        &root,
+        ObjectType::CapTableObject,
+        2,
+        7,
+        1,
+    );
+    check(&root, &untyped, &kernel);
+    arena.restore(&snapshot);
+    check(&root, &untyped, &kernel);
+    assert_eq!(addresses(scan(&root)), addresses((0..4).map(|i| &*slot(i))));
+
+    let base = untyped.get_untyped_ptr();
+    let end = base + BIT!(untyped.get_untyped_block_size());
+    drop(arena);
+    #[cfg(feature = "region_index")]
+    assert!(sel4_cspace::interface::caps_overlapping(base, end).is_empty());
+    #[cfg(feature = "object_index")]
+    for &(object_type, ptr) in &kernel.created {
+        let user_size = if object_type == ObjectType::CapTableObject {
+            2
+        } else {
+            0
+        };
+        assert!(sel4_cspace::interface::slots_of_object_at(object_type, ptr, user_size).is_empty());
+    }
+    let _ = (base, end);
+}
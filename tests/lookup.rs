@@ -0,0 +1,256 @@
+//! `resolve_address_bits` and the lookup family built on it, with the details of the lookup faults.
+#![cfg(feature = "hosted")]
+
+use sel4_common::fault::{
+    lookup_fault_depth_mismatch, lookup_fault_guard_mismatch, lookup_fault_invalid_root,
+    lookup_fault_missing_capability, lookup_fault_t,
+};
+use sel4_common::sel4_config::{seL4_DeleteFirst, seL4_FailedLookup, seL4_RangeError};
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::{cnode_slot, Arena};
+use sel4_cspace::interface::{
+    cap_t, ensure_empty_slot, ensure_non_empty_slot, lookupSlot_ret_t, lookup_cap,
+    lookup_cap_and_slot, lookup_empty_target_slot, lookup_non_empty_source_slot, lookup_pivot_slot,
+    lookup_slot, lookup_slot_for_cnode_op, lookup_source_slot, lookup_target_slot,
+    resolveAddressBits_ret_t, resolve_address_bits, CapTag,
+};
+
+/// A root CNode of 16 slots with the 4 bit guard 0x5, slot 3 holds a CNode of 4 slots with the
+/// 2 bit guard 0x1 and slot 5 an endpoint.
+fn cspace(arena: &mut Arena) -> cap_t {
+    let root = arena.new_cnode(4, 4, 0x5);
+    cnode_slot(&root, 3).cap = arena.new_cnode(2, 2, 0x1);
+    cnode_slot(&root, 5).cap = arena.new_endpoint();
+    root
+}
+
+fn fault(ret: &resolveAddressBits_ret_t) -> lookup_fault_t {
+    assert_eq!(ret.status, exception_t::EXCEPTION_LOOKUP_FAULT);
+    ret.lookupFault
+}
+
+#[test]
+fn resolve_two_levels() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 3).cap;
+    let ret = resolve_address_bits(&root, (0x53 << 4) | 0b01_10, 12);
+    assert_eq!(ret.status, exception_t::EXCEPTION_NONE);
+    assert_eq!(ret.slot, cnode_slot(&inner, 2) as *mut _);
+    assert_eq!(ret.bitsRemaining, 0);
+    // 只解析第一层
+    let ret = resolve_address_bits(&root, 0x53, 8);
+    assert_eq!(ret.slot, cnode_slot(&root, 3) as *mut _);
+    assert_eq!(ret.bitsRemaining, 0);
+    // 遇到不是`CNode`的`cap`时停止，返回剩余的位数
+    let ret = resolve_address_bits(&root, 0x55 << 4, 12);
+    assert_eq!(ret.status, exception_t::EXCEPTION_NONE);
+    assert_eq!(ret.slot, cnode_slot(&root, 5) as *mut _);
+    assert_eq!(ret.bitsRemaining, 4);
+}
+
+#[test]
+fn resolve_root_invalid() {
+    let mut arena = Arena::new(16);
+    let ep = arena.new_endpoint();
+    for root in [ep, cap_t::new_null_cap()] {
+        let ret = resolve_address_bits(&root, 0, 8);
+        let fault = fault(&ret);
+        assert_eq!(fault.get_type(), lookup_fault_invalid_root);
+        assert_eq!(ret.bitsRemaining, 8);
+    }
+}
+
+#[test]
+fn resolve_guard_mismatch() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    // `guard_found`是`CNode cap`的`guard`，`bits_left`是这一层开始时剩余的位数
+    let cases = [
+        ((0x6_3, 8), (0x5, 4, 8)),
+        ((0x5, 3), (0x5, 4, 3)),
+        (((0x53 << 4) | 0b10_00, 12), (0x1, 2, 4)),
+        ((0x53 << 1, 9), (0x1, 2, 1)),
+    ];
+    for ((cap_ptr, n_bits), (guard_found, bits_found, bits_left)) in cases {
+        let fault = fault(&resolve_address_bits(&root, cap_ptr, n_bits));
+        assert_eq!(fault.get_type(), lookup_fault_guard_mismatch);
+        assert_eq!(
+            (
+                fault.guard_mismatch_get_guard_found(),
+                fault.guard_mismatch_get_bits_found(),
+                fault.guard_mismatch_get_bits_left()
+            ),
+            (guard_found, bits_found, bits_left),
+            "{cap_ptr:#x} {n_bits}"
+        );
+    }
+}
+
+#[test]
+fn resolve_depth_mismatch() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    // 剩余位数足够匹配`guard`，但不足以索引`slot`
+    let cases = [
+        ((0x5 << 2, 6), (8, 6)),
+        (((0x53 << 3) | (0b01 << 1), 11), (4, 3)),
+    ];
+    for ((cap_ptr, n_bits), (bits_found, bits_left)) in cases {
+        let fault = fault(&resolve_address_bits(&root, cap_ptr, n_bits));
+        assert_eq!(fault.get_type(), lookup_fault_depth_mismatch);
+        assert_eq!(
+            (
+                fault.depth_mismatch_get_bits_found(),
+                fault.depth_mismatch_get_bits_left()
+            ),
+            (bits_found, bits_left),
+            "{cap_ptr:#x} {n_bits}"
+        );
+    }
+}
+
+/// The syscall error of a failed CNode lookup, with `failedLookupWasSource` checked.
+fn failed_lookup(ret: &lookupSlot_ret_t, is_source: bool) -> lookup_fault_t {
+    assert_eq!(ret.status, exception_t::EXCEPTION_SYSCALL_ERROR);
+    assert_eq!(ret.error._type, seL4_FailedLookup);
+    assert_eq!(ret.error.failedLookupWasSource, is_source as usize);
+    ret.error.lookupFault
+}
+
+#[test]
+fn lookup_slot_for_cnode_op_errors() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let ep = cnode_slot(&root, 5).cap;
+    for is_source in [true, false] {
+        let fault = failed_lookup(&lookup_slot_for_cnode_op(is_source, &ep, 0, 8), is_source);
+        assert_eq!(fault.get_type(), lookup_fault_invalid_root);
+
+        for depth in [0, 65] {
+            let ret = lookup_slot_for_cnode_op(is_source, &root, 0x53, depth);
+            assert_eq!(ret.status, exception_t::EXCEPTION_SYSCALL_ERROR);
+            assert_eq!(
+                (
+                    ret.error._type,
+                    ret.error.rangeErrorMin,
+                    ret.error.rangeErrorMax
+                ),
+                (seL4_RangeError, 1, 64)
+            );
+        }
+
+        let ret = lookup_slot_for_cnode_op(is_source, &root, 0x63, 8);
+        let fault = failed_lookup(&ret, is_source);
+        assert_eq!(fault.get_type(), lookup_fault_guard_mismatch);
+        assert_eq!(fault.guard_mismatch_get_bits_left(), 8);
+
+        // 解析停在不是`CNode`的`cap`上，必须用完`depth`位
+        let ret = lookup_slot_for_cnode_op(is_source, &root, 0x55 << 4, 12);
+        let fault = failed_lookup(&ret, is_source);
+        assert_eq!(fault.get_type(), lookup_fault_depth_mismatch);
+        assert_eq!(
+            (
+                fault.depth_mismatch_get_bits_found(),
+                fault.depth_mismatch_get_bits_left()
+            ),
+            (0, 4)
+        );
+    }
+}
+
+#[test]
+fn lookup_source_target_and_pivot() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 3).cap;
+    let cap_ptr = (0x53 << 4) | 0b01_10;
+    for (lookup, is_source) in [
+        (
+            lookup_source_slot as fn(&cap_t, usize, usize) -> lookupSlot_ret_t,
+            true,
+        ),
+        (lookup_target_slot, false),
+        (lookup_pivot_slot, true),
+    ] {
+        let ret = lookup(&root, cap_ptr, 12);
+        assert_eq!(ret.status, exception_t::EXCEPTION_NONE);
+        assert_eq!(ret.slot, cnode_slot(&inner, 2) as *mut _);
+        let ret = lookup(&root, 0x63, 8);
+        assert_eq!(
+            failed_lookup(&ret, is_source).get_type(),
+            lookup_fault_guard_mismatch
+        );
+    }
+}
+
+#[test]
+fn ensure_empty_and_non_empty() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let (empty, full) = (cnode_slot(&root, 0), cnode_slot(&root, 5));
+
+    let ret = ensure_empty_slot(empty);
+    assert_eq!(ret.status, exception_t::EXCEPTION_NONE);
+    assert_eq!(ret.slot, empty as *mut _);
+    let ret = ensure_empty_slot(full);
+    assert_eq!(ret.status, exception_t::EXCEPTION_SYSCALL_ERROR);
+    assert_eq!(ret.error._type, seL4_DeleteFirst);
+
+    let ret = ensure_non_empty_slot(true, full, 8);
+    assert_eq!(ret.status, exception_t::EXCEPTION_NONE);
+    assert_eq!(ret.slot, full as *mut _);
+    for is_source in [true, false] {
+        let fault = failed_lookup(&ensure_non_empty_slot(is_source, empty, 8), is_source);
+        assert_eq!(fault.get_type(), lookup_fault_missing_capability);
+        assert_eq!(fault.missing_cap_get_bits_left(), 8);
+    }
+
+    let ret = lookup_empty_target_slot(&root, 0x55, 8);
+    assert_eq!(ret.error._type, seL4_DeleteFirst);
+    let ret = lookup_empty_target_slot(&root, 0x50, 8);
+    assert_eq!(ret.slot, empty as *mut _);
+    let fault = failed_lookup(&lookup_non_empty_source_slot(&root, 0x50, 8), true);
+    assert_eq!(fault.get_type(), lookup_fault_missing_capability);
+    let ret = lookup_non_empty_source_slot(&root, 0x55, 8);
+    assert_eq!(ret.slot, full as *mut _);
+    // 查找失败时不检查`slot`
+    let ret = lookup_empty_target_slot(&root, 0x65, 8);
+    assert_eq!(
+        failed_lookup(&ret, false).get_type(),
+        lookup_fault_guard_mismatch
+    );
+}
+
+#[test]
+fn lookup_with_all_bits() {
+    let mut arena = Arena::new(16);
+    let root = arena.new_cnode(4, 60, 0);
+    let ep = arena.new_endpoint();
+    cnode_slot(&root, 5).cap = ep;
+
+    let ret = lookup_slot(&root, 5);
+    assert_eq!(ret.status, exception_t::EXCEPTION_NONE);
+    assert_eq!(ret.slot, cnode_slot(&root, 5) as *mut _);
+    let ret = lookup_cap(&root, 5);
+    assert_eq!(ret.cap.words, ep.words);
+    let ret = lookup_cap_and_slot(&root, 5);
+    assert_eq!(
+        (ret.cap.words, ret.slot),
+        (ep.words, cnode_slot(&root, 5) as *mut _)
+    );
+
+    // 高位不匹配`guard`
+    let cap_ptr = (1 << 63) | 5;
+    let ret = lookup_slot(&root, cap_ptr);
+    assert_eq!(ret.status, exception_t::EXCEPTION_LOOKUP_FAULT);
+    assert_eq!(ret.lookupFault.get_type(), lookup_fault_guard_mismatch);
+    let ret = lookup_cap(&root, cap_ptr);
+    assert_eq!(ret.status, exception_t::EXCEPTION_LOOKUP_FAULT);
+    assert_eq!(ret.cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(ret.lookupFault.guard_mismatch_get_bits_left(), 64);
+    let ret = lookup_cap_and_slot(&root, cap_ptr);
+    assert_eq!(ret.status, exception_t::EXCEPTION_LOOKUP_FAULT);
+    assert!(ret.slot.is_null());
+    assert_eq!(ret.cap.get_cap_type(), CapTag::CapNullCap);
+}
@@ -0,0 +1,164 @@
+//! Tests of the mapping database, run with `cargo test --features hosted`.
+#![cfg(feature = "hosted")]
+
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, check_invariants, cte_insert, cte_rotate, cte_swap, cte_t, mdb_node_t, CapTag, Unbounded,
+};
+
+#[test]
+fn first_badged_is_stored() {
+    let mut mdb = mdb_node_t::new(0, 0, 0, 0);
+    mdb.set_first_badged(1);
+    assert_eq!(mdb.get_first_badged(), 1);
+    mdb.set_revocable(1);
+    mdb.set_first_badged(0);
+    assert_eq!(mdb.get_first_badged(), 0);
+    assert_eq!(mdb.get_revocable(), 1);
+}
+
+/// Two caps minted with the same badge from one endpoint cap are siblings: the second one is not
+/// the parent of the first one and its copies, which follow it in the list.
+#[test]
+fn badged_endpoint_parent() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(4, 0, 0);
+    let original = cnode_slot(&root, 0);
+    original.cap = arena.new_endpoint();
+    original.cteMDBNode.set_revocable(1);
+    let mut badged = original.cap;
+    badged.set_ep_badge(7);
+
+    cte_insert(&badged, original, cnode_slot(&root, 1));
+    cte_insert(&badged, cnode_slot(&root, 1), cnode_slot(&root, 2));
+    cte_insert(&badged, original, cnode_slot(&root, 3));
+    // 链表顺序为 0, 3, 1, 2
+    assert_eq!(cnode_slot(&root, 1).cteMDBNode.get_first_badged(), 1);
+    assert_eq!(cnode_slot(&root, 2).cteMDBNode.get_first_badged(), 0);
+    assert_eq!(cnode_slot(&root, 3).cteMDBNode.get_first_badged(), 1);
+
+    // 若`firstBadged`读出总为0，3号会被当作1号和2号的父节点
+    assert!(cnode_slot(&root, 3).ensure_no_children() == exception_t::EXCEPTION_NONE);
+    assert!(cnode_slot(&root, 1).ensure_no_children() != exception_t::EXCEPTION_NONE);
+    cnode_slot(&root, 3).revoke_with(&mut kernel, &mut Unbounded);
+    assert_eq!(cnode_slot(&root, 1).cap.get_ep_badge(), 7);
+    assert_eq!(cnode_slot(&root, 2).cap.get_ep_badge(), 7);
+
+    cnode_slot(&root, 1).revoke_with(&mut kernel, &mut Unbounded);
+    assert_eq!(cnode_slot(&root, 1).cap.get_ep_badge(), 7);
+    assert_eq!(cnode_slot(&root, 2).cap.get_cap_type(), CapTag::CapNullCap);
+    assert!(original.ensure_no_children() != exception_t::EXCEPTION_NONE);
+}
+
+/// Slot 0 holds an original endpoint with the copies in slots 1 and 2, slot 5 another one with a
+/// copy in slot 6.
+fn two_endpoints(arena: &mut Arena) -> cap_t {
+    let root = arena.new_cnode(4, 0, 0);
+    for (original, copies) in [(0, [1, 2]), (5, [6, 6])] {
+        let slot = cnode_slot(&root, original);
+        slot.cap = arena.new_endpoint();
+        slot.cteMDBNode.set_revocable(1);
+        let cap = slot.cap;
+        for i in copies {
+            if cnode_slot(&root, i).cap.get_cap_type() == CapTag::CapNullCap {
+                cte_insert(&cap, cnode_slot(&root, original), cnode_slot(&root, i));
+            }
+        }
+    }
+    root
+}
+
+fn parent(root: &cap_t, index: usize) -> Option<usize> {
+    cnode_slot(root, index).parent().map(|slot| slot.get_ptr())
+}
+
+fn assert_mdb_ok(root: &cap_t, slots: &[usize]) {
+    let slots: Vec<_> = slots
+        .iter()
+        .map(|&i| cnode_slot(root, i) as *const cte_t)
+        .collect();
+    assert_eq!(check_invariants(&slots, |v| panic!("{v:?}")), 0);
+}
+
+#[test]
+fn rotate_distinct_slots() {
+    let mut arena = Arena::new(16);
+    let root = two_endpoints(&mut arena);
+    let (a, b) = (cnode_slot(&root, 0).cap, cnode_slot(&root, 5).cap);
+    cte_rotate(
+        &a,
+        cnode_slot(&root, 0),
+        &b,
+        cnode_slot(&root, 5),
+        cnode_slot(&root, 8),
+    );
+    assert_eq!(cnode_slot(&root, 0).cap.get_cap_type(), CapTag::CapNullCap);
+    assert_eq!(cnode_slot(&root, 5).cap.words, a.words);
+    assert_eq!(cnode_slot(&root, 8).cap.words, b.words);
+    let (slot5, slot8) = (
+        cnode_slot(&root, 5).get_ptr(),
+        cnode_slot(&root, 8).get_ptr(),
+    );
+    assert_eq!(
+        (parent(&root, 1), parent(&root, 2)),
+        (Some(slot5), Some(slot5))
+    );
+    assert_eq!(parent(&root, 6), Some(slot8));
+    assert_eq!(cnode_slot(&root, 5).cteMDBNode.get_revocable(), 1);
+    assert_mdb_ok(&root, &[5, 8]);
+}
+
+/// The second slot follows the first one in the list (copies are inserted right after the
+/// original), the first move relinks the first slot.
+#[test]
+fn rotate_adjacent_slots() {
+    let mut arena = Arena::new(16);
+    let root = two_endpoints(&mut arena);
+    let (a, copy) = (cnode_slot(&root, 0).cap, cnode_slot(&root, 2).cap);
+    assert_eq!(
+        cnode_slot(&root, 0).cteMDBNode.get_next(),
+        cnode_slot(&root, 2).get_ptr()
+    );
+    cte_rotate(
+        &a,
+        cnode_slot(&root, 0),
+        &copy,
+        cnode_slot(&root, 2),
+        cnode_slot(&root, 9),
+    );
+    assert_eq!(cnode_slot(&root, 0).cap.get_cap_type(), CapTag::CapNullCap);
+    let slot2 = cnode_slot(&root, 2).get_ptr();
+    assert_eq!(
+        (parent(&root, 9), parent(&root, 1)),
+        (Some(slot2), Some(slot2))
+    );
+    assert_mdb_ok(&root, &[2, 5]);
+}
+
+/// Rotating a slot into itself is a swap.
+#[test]
+fn rotate_into_itself_swaps() {
+    let mut arena = Arena::new(16);
+    let root = two_endpoints(&mut arena);
+    let (a, b) = (cnode_slot(&root, 0).cap, cnode_slot(&root, 5).cap);
+    cte_swap(&a, cnode_slot(&root, 0), &b, cnode_slot(&root, 5));
+    assert_eq!(cnode_slot(&root, 0).cap.words, b.words);
+    assert_eq!(cnode_slot(&root, 5).cap.words, a.words);
+    let (slot0, slot5) = (
+        cnode_slot(&root, 0).get_ptr(),
+        cnode_slot(&root, 5).get_ptr(),
+    );
+    assert_eq!(
+        (parent(&root, 1), parent(&root, 6)),
+        (Some(slot5), Some(slot0))
+    );
+    assert_mdb_ok(&root, &[0, 5]);
+
+    // 相邻的两个`slot`
+    let (b, copy) = (cnode_slot(&root, 0).cap, cnode_slot(&root, 6).cap);
+    cte_swap(&b, cnode_slot(&root, 0), &copy, cnode_slot(&root, 6));
+    assert_eq!(parent(&root, 0), Some(cnode_slot(&root, 6).get_ptr()));
+    assert_mdb_ok(&root, &[0, 5]);
+}
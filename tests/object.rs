@@ -0,0 +1,168 @@
+//! The sizes of the object types and the caps to new objects.
+#![cfg(feature = "hosted")]
+
+use sel4_common::object::{seL4_ObjectTypeCount, ObjectType};
+use sel4_common::sel4_config::{
+    asidInvalid, seL4_EndpointBits, seL4_HugePageBits, seL4_LargePageBits, seL4_NotificationBits,
+    seL4_PageBits, seL4_SlotBits, seL4_TCBBits, RISCV_4K_Page, RISCV_Giga_Page, RISCV_Mega_Page,
+    PT_SIZE_BITS, TCB_OFFSET,
+};
+use sel4_cspace::interface::{
+    cap_for_new_object, object_size_bits, vm_rights_t, CapTag, ObjectKey,
+};
+
+/// Aligned for every object type, including giga pages.
+const PTR: usize = 0xffff_ffc0_4000_0000;
+
+/// Every object type with a `user_size`, its size bits and the tag of its caps.
+fn table() -> [(ObjectType, usize, usize, CapTag); 10] {
+    [
+        (ObjectType::UnytpedObject, 16, 16, CapTag::CapUntypedCap),
+        (ObjectType::TCBObject, 0, seL4_TCBBits, CapTag::CapThreadCap),
+        (
+            ObjectType::EndpointObject,
+            0,
+            seL4_EndpointBits,
+            CapTag::CapEndpointCap,
+        ),
+        (
+            ObjectType::NotificationObject,
+            0,
+            seL4_NotificationBits,
+            CapTag::CapNotificationCap,
+        ),
+        (
+            ObjectType::CapTableObject,
+            0,
+            seL4_SlotBits,
+            CapTag::CapCNodeCap,
+        ),
+        (
+            ObjectType::CapTableObject,
+            6,
+            seL4_SlotBits + 6,
+            CapTag::CapCNodeCap,
+        ),
+        (
+            ObjectType::GigaPageObject,
+            0,
+            seL4_HugePageBits,
+            CapTag::CapFrameCap,
+        ),
+        (
+            ObjectType::NormalPageObject,
+            0,
+            seL4_PageBits,
+            CapTag::CapFrameCap,
+        ),
+        (
+            ObjectType::MegaPageObject,
+            0,
+            seL4_LargePageBits,
+            CapTag::CapFrameCap,
+        ),
+        (
+            ObjectType::PageTableObject,
+            0,
+            PT_SIZE_BITS,
+            CapTag::CapPageTableCap,
+        ),
+    ]
+}
+
+#[test]
+fn table_covers_all_types() {
+    for word in 0..seL4_ObjectTypeCount {
+        let object_type = ObjectType::from_usize(word).unwrap();
+        assert!(table().iter().any(|row| row.0 == object_type), "{word}");
+    }
+    assert_eq!(ObjectType::from_usize(seL4_ObjectTypeCount), None);
+}
+
+#[test]
+fn new_object_caps() {
+    for (object_type, user_size, size_bits, tag) in table() {
+        assert_eq!(object_size_bits(object_type, user_size), size_bits);
+        let cap = cap_for_new_object(object_type, PTR, user_size, false);
+        assert_eq!(cap.get_cap_type(), tag, "{object_type:?}");
+        // `cap`所指的内存正好是新对象
+        let region = cap.object_region().unwrap();
+        assert_eq!(
+            (region.base, region.size_bits),
+            (PTR, size_bits),
+            "{object_type:?}"
+        );
+        assert_eq!(
+            ObjectKey::of_object(object_type, PTR, user_size).region(),
+            region
+        );
+    }
+
+    let tcb = cap_for_new_object(ObjectType::TCBObject, PTR, 0, false);
+    assert_eq!(tcb.get_tcb_ptr(), PTR + TCB_OFFSET);
+    let cnode = cap_for_new_object(ObjectType::CapTableObject, PTR, 6, false);
+    assert_eq!(
+        (
+            cnode.get_cnode_radix(),
+            cnode.get_cnode_guard_size(),
+            cnode.get_cnode_guard()
+        ),
+        (6, 0, 0)
+    );
+    let untyped = cap_for_new_object(ObjectType::UnytpedObject, PTR, 16, false);
+    assert_eq!(
+        (
+            untyped.get_untyped_block_size(),
+            untyped.get_untyped_free_index()
+        ),
+        (16, 0)
+    );
+    let ep = cap_for_new_object(ObjectType::EndpointObject, PTR, 0, false);
+    assert_eq!(ep.get_ep_badge(), 0);
+    assert_eq!((ep.get_ep_can_send(), ep.get_ep_can_receive()), (1, 1));
+    let pt = cap_for_new_object(ObjectType::PageTableObject, PTR, 0, false);
+    assert_eq!(
+        (pt.get_pt_mapped_asid(), pt.get_pt_is_mapped()),
+        (asidInvalid, 0)
+    );
+}
+
+#[test]
+fn new_frame_caps() {
+    for (object_type, frame_size) in [
+        (ObjectType::NormalPageObject, RISCV_4K_Page),
+        (ObjectType::MegaPageObject, RISCV_Mega_Page),
+        (ObjectType::GigaPageObject, RISCV_Giga_Page),
+    ] {
+        for is_device in [false, true] {
+            let cap = cap_for_new_object(object_type, PTR, 0, is_device);
+            assert_eq!(cap.get_frame_size(), frame_size);
+            assert_eq!(cap.get_frame_is_device(), is_device as usize);
+            assert_eq!(
+                (cap.get_frame_mapped_asid(), cap.get_frame_mapped_address()),
+                (asidInvalid, 0)
+            );
+            assert_eq!(cap.get_frame_vm_rights(), vm_rights_t::VMReadWrite as usize);
+        }
+    }
+}
+
+#[test]
+fn new_device_untyped() {
+    for is_device in [false, true] {
+        let cap = cap_for_new_object(ObjectType::UnytpedObject, PTR, 20, is_device);
+        assert_eq!(cap.get_untyped_is_device(), is_device as usize);
+        assert_eq!(cap.get_untyped_block_size(), 20);
+        assert_eq!(cap.get_untyped_ptr(), PTR);
+    }
+    // 其他对象忽略`is_device`
+    for (object_type, user_size, _, _) in table() {
+        if object_type.is_arch_type() || object_type == ObjectType::UnytpedObject {
+            continue;
+        }
+        assert_eq!(
+            cap_for_new_object(object_type, PTR, user_size, true).words,
+            cap_for_new_object(object_type, PTR, user_size, false).words
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! `object_region` and the region and identity checks built on it, for every pair of `CapTag`s.
+#![cfg(feature = "hosted")]
+
+use sel4_cspace::interface::{
+    cap_t, same_object_as, same_region_as, CapTag, ObjectRegion, ZombieCap, ZombieKind,
+};
+
+const BASE: usize = 0x8000_0000;
+/// The TCB object is at `BASE + 0x2000`, its pointer at the `TCB_OFFSET`.
+const TCB: usize = BASE + 0x2200;
+
+/// `(name, cap, object_region, get_cap_ptr)`
+type Case = (&'static str, cap_t, Option<(usize, usize)>, usize);
+
+/// A cap of every type, several for some types.
+fn caps() -> Vec<Case> {
+    let zombie = |kind, ptr, number| cap_t::from(ZombieCap::new(kind, ptr, number).unwrap());
+    let mut ep_badged = cap_t::new_endpoint_cap(0, 1, 1, 1, 1, BASE + 0x10);
+    ep_badged.set_ep_badge(7);
+    let mut nf_badged = cap_t::new_notification_cap(0, 1, 1, BASE + 0x40);
+    nf_badged.set_nf_badge(3);
+    vec![
+        ("null", cap_t::new_null_cap(), None, 0),
+        (
+            "ut",
+            cap_t::new_untyped_cap(0, 0, 24, BASE),
+            Some((BASE, 24)),
+            BASE,
+        ),
+        (
+            "ut_child",
+            cap_t::new_untyped_cap(0, 0, 21, BASE + 0x200000),
+            Some((BASE + 0x200000, 21)),
+            BASE + 0x200000,
+        ),
+        (
+            "ep",
+            cap_t::new_endpoint_cap(0, 1, 1, 1, 1, BASE + 0x10),
+            Some((BASE + 0x10, 4)),
+            BASE + 0x10,
+        ),
+        ("ep_badged", ep_badged, Some((BASE + 0x10, 4)), BASE + 0x10),
+        (
+            "ep_other",
+            cap_t::new_endpoint_cap(0, 1, 1, 1, 1, BASE + 0x20),
+            Some((BASE + 0x20, 4)),
+            BASE + 0x20,
+        ),
+        (
+            "nf",
+            cap_t::new_notification_cap(0, 1, 1, BASE + 0x40),
+            Some((BASE + 0x40, 4)),
+            BASE + 0x40,
+        ),
+        ("nf_badged", nf_badged, Some((BASE + 0x40, 4)), BASE + 0x40),
+        (
+            "reply",
+            cap_t::new_reply_cap(0, 1, TCB),
+            Some((BASE + 0x2000, 10)),
+            TCB,
+        ),
+        (
+            "cnode",
+            cap_t::new_cnode_cap(4, 0, 0, BASE + 0x1000),
+            Some((BASE + 0x1000, 9)),
+            BASE + 0x1000,
+        ),
+        (
+            "tcb",
+            cap_t::new_thread_cap(TCB),
+            Some((BASE + 0x2000, 10)),
+            TCB,
+        ),
+        ("irqc", cap_t::new_irq_control_cap(), None, 0),
+        ("irqh5", cap_t::new_irq_handler_cap(5), None, 0),
+        ("irqh6", cap_t::new_irq_handler_cap(6), None, 0),
+        (
+            "zombie",
+            zombie(ZombieKind::CNode { radix: 4 }, BASE + 0x1000, 16),
+            Some((BASE + 0x1000, 9)),
+            BASE + 0x1000,
+        ),
+        (
+            "tcb_zombie",
+            zombie(ZombieKind::Tcb, BASE + 0x2000, 5),
+            Some((BASE + 0x2000, 10)),
+            BASE + 0x2000,
+        ),
+        ("domain", cap_t::new_domain_cap(), None, 0),
+        (
+            "frame4k",
+            cap_t::new_frame_cap(0, BASE + 0x201000, 0, 3, 0, 0),
+            Some((BASE + 0x201000, 12)),
+            BASE + 0x201000,
+        ),
+        (
+            "frame2m",
+            cap_t::new_frame_cap(0, BASE + 0x200000, 1, 3, 0, 0),
+            Some((BASE + 0x200000, 21)),
+            BASE + 0x200000,
+        ),
+        (
+            "frame_out",
+            cap_t::new_frame_cap(0, BASE + 0x1000000, 0, 3, 0, 0),
+            Some((BASE + 0x1000000, 12)),
+            BASE + 0x1000000,
+        ),
+        (
+            "pt",
+            cap_t::new_page_table_cap(0, BASE + 0x20000, 0, 0),
+            Some((BASE + 0x20000, 12)),
+            BASE + 0x20000,
+        ),
+        ("asidc", cap_t::new_asid_control_cap(), None, 0),
+        (
+            "pool",
+            cap_t::new_asid_pool_cap(0, BASE + 0x30000),
+            Some((BASE + 0x30000, 12)),
+            BASE + 0x30000,
+        ),
+    ]
+}
+
+/// For each cap, the caps `same_region_as` it, all the others are not.
+const SAME_REGION: &[(&str, &[&str])] = &[
+    (
+        "ut",
+        &[
+            "ut",
+            "ut_child",
+            "ep",
+            "ep_badged",
+            "ep_other",
+            "nf",
+            "nf_badged",
+            "cnode",
+            "tcb",
+            "zombie",
+            "tcb_zombie",
+            "frame4k",
+            "frame2m",
+            "pt",
+            "pool",
+        ],
+    ),
+    ("ut_child", &["ut_child", "frame4k", "frame2m"]),
+    ("ep", &["ep", "ep_badged"]),
+    ("ep_badged", &["ep", "ep_badged"]),
+    ("ep_other", &["ep_other"]),
+    ("nf", &["nf", "nf_badged"]),
+    ("nf_badged", &["nf", "nf_badged"]),
+    ("reply", &["reply"]),
+    ("cnode", &["cnode"]),
+    ("tcb", &["tcb"]),
+    ("irqc", &["irqc", "irqh5", "irqh6"]),
+    ("irqh5", &["irqh5"]),
+    ("irqh6", &["irqh6"]),
+    ("domain", &["domain"]),
+    ("frame4k", &["frame4k"]),
+    ("frame2m", &["frame2m", "frame4k"]),
+    ("frame_out", &["frame_out"]),
+    ("pt", &["pt"]),
+    ("asidc", &["asidc"]),
+    ("pool", &["pool"]),
+];
+
+/// The pairs `same_region_as` but not `same_object_as`: an untyped is never the same object as
+/// a cap, nor the IRQ control cap as a handler, nor a frame as a smaller frame inside it.
+const NOT_SAME_OBJECT: &[(&str, &str)] =
+    &[("irqc", "irqh5"), ("irqc", "irqh6"), ("frame2m", "frame4k")];
+
+fn expected(table: &[(&str, &[&str])], name1: &str, name2: &str) -> bool {
+    table
+        .iter()
+        .any(|(name, names)| *name == name1 && names.contains(&name2))
+}
+
+#[test]
+fn every_cap_type_is_covered() {
+    let mut tags: Vec<_> = caps()
+        .iter()
+        .map(|(_, cap, _, _)| cap.get_cap_type() as usize)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    assert_eq!(tags.len(), 15);
+}
+
+#[test]
+fn object_region_size_and_ptr() {
+    for (name, cap, region, ptr) in caps() {
+        let region = region.map(|(base, size_bits)| ObjectRegion { base, size_bits });
+        assert_eq!(cap.object_region(), region, "{name}");
+        assert_eq!(
+            cap.object_size_bits(),
+            region.map(|r| r.size_bits),
+            "{name}"
+        );
+        assert_eq!(
+            cap.get_cap_size_bits(),
+            region.map_or(0, |r| r.size_bits),
+            "{name}"
+        );
+        assert_eq!(cap.get_cap_ptr(), ptr, "{name}");
+    }
+}
+
+#[test]
+fn contains_and_overlaps() {
+    let caps = caps();
+    for (name1, cap1, _, _) in &caps {
+        for (name2, cap2, _, _) in &caps {
+            let (Some(a), Some(b)) = (cap1.object_region(), cap2.object_region()) else {
+                continue;
+            };
+            assert_eq!(a.overlaps(&b), b.overlaps(&a), "{name1} {name2}");
+            assert!(!a.contains(&b) || a.overlaps(&b), "{name1} {name2}");
+            assert_eq!(a.contains(&b) && b.contains(&a), a == b, "{name1} {name2}");
+        }
+    }
+    let region = |name: &str| {
+        caps.iter()
+            .find(|(n, _, _, _)| *n == name)
+            .and_then(|(_, cap, _, _)| cap.object_region())
+            .unwrap()
+    };
+    // `untyped`包含其中的`frame`，不包含紧接在其后的`frame`
+    assert!(region("ut").contains(&region("frame4k")));
+    assert!(region("ut_child").contains(&region("frame2m")));
+    assert!(!region("ut").overlaps(&region("frame_out")));
+    assert!(!region("ut_child").contains(&region("ut")));
+    assert!(region("frame2m").overlaps(&region("frame4k")));
+    // 相邻的对象互不重叠
+    assert!(!region("ep").overlaps(&region("ep_other")));
+    // `TCB`、`reply`和`TCB zombie`都覆盖整个`TCB`对象
+    assert_eq!(region("tcb"), region("reply"));
+    assert_eq!(region("tcb"), region("tcb_zombie"));
+    assert_eq!(region("cnode"), region("zombie"));
+}
+
+#[test]
+fn same_region_and_same_object() {
+    let caps = caps();
+    for (name1, cap1, _, _) in &caps {
+        for (name2, cap2, _, _) in &caps {
+            let region = expected(SAME_REGION, name1, name2);
+            assert_eq!(same_region_as(cap1, cap2), region, "{name1} {name2}");
+            let object =
+                region && !name1.starts_with("ut") && !NOT_SAME_OBJECT.contains(&(*name1, *name2));
+            assert_eq!(same_object_as(cap1, cap2), object, "{name1} {name2}");
+        }
+    }
+}
+
+#[test]
+fn badges_do_not_change_the_object() {
+    let caps = caps();
+    let cap = |name: &str| caps.iter().find(|(n, _, _, _)| *n == name).unwrap().1;
+    for (unbadged, badged) in [("ep", "ep_badged"), ("nf", "nf_badged")] {
+        let (unbadged, badged) = (cap(unbadged), cap(badged));
+        assert_ne!(unbadged.words, badged.words);
+        let mut rebadged = badged;
+        match badged.get_cap_type() {
+            CapTag::CapEndpointCap => rebadged.set_ep_badge(1),
+            _ => rebadged.set_nf_badge(1),
+        }
+        for other in [unbadged, rebadged] {
+            assert!(same_region_as(&badged, &other) && same_object_as(&badged, &other));
+            assert!(same_region_as(&other, &badged) && same_object_as(&other, &badged));
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Preempted deletions and revocations restarted at every work unit, see `hosted::restart`.
+#![cfg(feature = "hosted")]
+
+use sel4_common::message_info::MessageLabel;
+use sel4_common::object::ObjectType;
+use sel4_common::sel4_config::{tcbCTable, tcbVTable};
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::restart::{check_restartability, RestartReport};
+use sel4_cspace::hosted::{cnode_slot, tcb_slot, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, cte_insert, cte_t, decode_untyped_invocation, invoke_untyped_retype, Unbounded,
+    ZombieCap, ZombieKind,
+};
+
+fn assert_restartable(report: RestartReport) {
+    assert_eq!(report.status, exception_t::EXCEPTION_NONE);
+    assert!(report.work_units > 0);
+    assert_eq!(report.trials, 2 * report.work_units);
+}
+
+/// `seL4_Untyped_Retype` of the untyped in `slot` into `root`, `args` are the message registers.
+fn retype(kernel: &mut HostedKernel, slot: *mut cte_t, root: &cap_t, args: &[usize]) {
+    let r = decode_untyped_invocation(MessageLabel::UntypedRetype, slot, args, &[*root]).unwrap();
+    let status = invoke_untyped_retype(&r, kernel, &mut Unbounded);
+    assert_eq!(status, exception_t::EXCEPTION_NONE);
+}
+
+#[test]
+fn delete_all_nested_cnodes() {
+    let report = check_restartability(
+        20,
+        |arena| {
+            let root = arena.new_cnode(3, 0, 0);
+            let outer = arena.new_cnode(3, 0, 0);
+            let inner = arena.new_cnode(2, 0, 0);
+            let innermost = arena.new_cnode(1, 0, 0);
+            let slot = cnode_slot(&root, 0);
+            slot.cap = outer;
+            slot.cteMDBNode.set_revocable(1);
+            cnode_slot(&outer, 7).cap = inner;
+            cnode_slot(&outer, 1).cap = cap_t::new_irq_handler_cap(4);
+            cnode_slot(&inner, 0).cap = innermost;
+            cnode_slot(&inner, 3).cap = arena.new_notification();
+            cnode_slot(&innermost, 1).cap = arena.new_endpoint();
+            // `outer`中的副本的父节点在`root`中，删除后`root`中的仍然存在
+            let ep = cnode_slot(&root, 2);
+            ep.cap = arena.new_endpoint();
+            ep.cteMDBNode.set_revocable(1);
+            let cap = ep.cap;
+            cte_insert(&cap, ep, cnode_slot(&outer, 5));
+            (root, slot as *mut cte_t)
+        },
+        |slot, kernel, budget| slot.delete_all_with(true, kernel, budget),
+    )
+    .unwrap();
+    assert_restartable(report);
+}
+
+#[test]
+fn delete_cyclic_zombie() {
+    let report = check_restartability(
+        20,
+        |arena| {
+            let root = arena.new_cnode(2, 0, 0);
+            let cnode = arena.new_cnode(3, 0, 0);
+            let inner = arena.new_cnode(1, 0, 0);
+            cnode_slot(&root, 1).cap = cnode;
+            // 非立即删除`cnode`后留下的状态：第一个`slot`中是指向自身的`zombie`
+            let first = cnode_slot(&cnode, 0);
+            let kind = ZombieKind::CNode { radix: 3 };
+            first.cap = ZombieCap::new(kind, first.get_ptr(), kind.slots())
+                .unwrap()
+                .into();
+            cnode_slot(&cnode, 2).cap = arena.new_endpoint();
+            cnode_slot(&cnode, 6).cap = inner;
+            cnode_slot(&inner, 1).cap = arena.new_notification();
+            (root, first as *mut cte_t)
+        },
+        |slot, kernel, budget| slot.delete_all_with(true, kernel, budget),
+    )
+    .unwrap();
+    assert_restartable(report);
+}
+
+#[test]
+fn delete_tcb() {
+    let report = check_restartability(
+        20,
+        |arena| {
+            let root = arena.new_cnode(2, 0, 0);
+            let tcb = arena.new_tcb();
+            let cspace = arena.new_cnode(2, 0, 0);
+            let slot = cnode_slot(&root, 3);
+            slot.cap = tcb;
+            cnode_slot(&cspace, 1).cap = arena.new_endpoint();
+            tcb_slot(&tcb, tcbCTable).cap = cspace;
+            tcb_slot(&tcb, tcbVTable).cap = arena.new_notification();
+            (root, slot as *mut cte_t)
+        },
+        |slot, kernel, budget| slot.delete_all_with(true, kernel, budget),
+    )
+    .unwrap();
+    assert_restartable(report);
+}
+
+#[test]
+fn revoke_retyped_untyped() {
+    let report = check_restartability(
+        22,
+        |arena| {
+            let mut kernel = HostedKernel::default();
+            let root = arena.new_cnode(5, 59, 0);
+            let slot = cnode_slot(&root, 0);
+            slot.cap = arena.new_untyped(18);
+            slot.cteMDBNode.set_revocable(1);
+            let slot = slot as *mut cte_t;
+            for (object_type, user_size, offset, window) in [
+                (ObjectType::EndpointObject, 0, 4, 3),
+                (ObjectType::TCBObject, 0, 7, 2),
+                (ObjectType::CapTableObject, 3, 9, 1),
+                (ObjectType::UnytpedObject, 12, 10, 1),
+            ] {
+                let args = [object_type as usize, user_size, 0, 0, offset, window];
+                retype(&mut kernel, slot, &root, &args);
+            }
+            // 新对象中再放入`cap`：`CNode`里有一个`endpoint`副本，`TCB`的`CSpace`是这个`CNode`
+            let cnode = cnode_slot(&root, 9).cap;
+            let ep = cnode_slot(&root, 4).cap;
+            cte_insert(&ep, cnode_slot(&root, 4), cnode_slot(&cnode, 5));
+            let tcb = cnode_slot(&root, 7).cap;
+            cte_insert(&cnode, cnode_slot(&root, 9), tcb_slot(&tcb, tcbCTable));
+            // 子`untyped`上再次`retype`
+            let child = cnode_slot(&root, 10);
+            let args = [ObjectType::NotificationObject as usize, 0, 0, 0, 12, 2];
+            retype(&mut kernel, child, &root, &args);
+            (root, slot)
+        },
+        |slot, kernel, budget| slot.revoke_with(kernel, budget),
+    )
+    .unwrap();
+    assert_restartable(report);
+}
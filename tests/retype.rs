@@ -0,0 +1,224 @@
+//! Untyped retype: argument checks, memory accounting, device untypeds and reset.
+#![cfg(feature = "hosted")]
+
+use sel4_common::message_info::MessageLabel;
+use sel4_common::object::ObjectType;
+use sel4_common::sel4_config::{seL4_InvalidArgument, seL4_NotEnoughMemory, seL4_RangeError};
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::restart::check_restartability;
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{
+    cap_t, cte_t, decode_untyped_invocation, invoke_untyped_retype, syscall_error_t, CapTag,
+    FixedBudget, Unbounded, UntypedRetype, CONFIG_RETYPE_FAN_OUT_LIMIT,
+};
+
+/// A root CNode of `BIT!(radix)` slots resolving with 64 bits, with an untyped of
+/// `BIT!(size_bits)` bytes in slot 0.
+fn setup(arena: &mut Arena, radix: usize, size_bits: usize, device: bool) -> (cap_t, *mut cte_t) {
+    let root = arena.new_cnode(radix, 64 - radix, 0);
+    let slot = cnode_slot(&root, 0);
+    slot.cap = cap_t::new_untyped_cap(0, device as usize, size_bits, arena.alloc(size_bits));
+    slot.cteMDBNode.set_revocable(1);
+    (root, slot)
+}
+
+/// Decode a retype of `window` objects into the slots of `root` from `offset`.
+fn decode(
+    slot: *mut cte_t,
+    root: &cap_t,
+    object_type: ObjectType,
+    user_size: usize,
+    offset: usize,
+    window: usize,
+) -> Result<UntypedRetype, syscall_error_t> {
+    let args = [object_type as usize, user_size, 0, 0, offset, window];
+    decode_untyped_invocation(MessageLabel::UntypedRetype, slot, &args, &[*root])
+}
+
+fn retype(
+    kernel: &mut HostedKernel,
+    slot: *mut cte_t,
+    root: &cap_t,
+    object_type: ObjectType,
+    offset: usize,
+    window: usize,
+) {
+    let r = decode(slot, root, object_type, 0, offset, window).unwrap();
+    assert!(invoke_untyped_retype(&r, kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+}
+
+fn free_index(slot: *mut cte_t) -> usize {
+    unsafe { (*slot).cap.get_untyped_free_index() }
+}
+
+#[test]
+fn fan_out_limit() {
+    let mut arena = Arena::new(24);
+    let mut kernel = HostedKernel::default();
+    let (root, slot) = setup(&mut arena, 9, 16, false);
+    let endpoint = ObjectType::EndpointObject;
+    for window in [0, CONFIG_RETYPE_FAN_OUT_LIMIT + 1] {
+        let err = decode(slot, &root, endpoint, 0, 1, window).unwrap_err();
+        assert_eq!(
+            (err._type, err.rangeErrorMin, err.rangeErrorMax),
+            (seL4_RangeError, 1, CONFIG_RETYPE_FAN_OUT_LIMIT)
+        );
+    }
+    // 窗口不能超出目标`CNode`
+    let err = decode(slot, &root, endpoint, 0, 500, 20).unwrap_err();
+    assert_eq!(
+        (err._type, err.rangeErrorMin, err.rangeErrorMax),
+        (seL4_RangeError, 1, 12)
+    );
+
+    retype(
+        &mut kernel,
+        slot,
+        &root,
+        endpoint,
+        1,
+        CONFIG_RETYPE_FAN_OUT_LIMIT,
+    );
+    assert_eq!(kernel.created.len(), CONFIG_RETYPE_FAN_OUT_LIMIT);
+    for i in 1..=CONFIG_RETYPE_FAN_OUT_LIMIT {
+        assert_eq!(
+            cnode_slot(&root, i).cap.get_cap_type(),
+            CapTag::CapEndpointCap
+        );
+    }
+    assert_eq!(
+        cnode_slot(&root, CONFIG_RETYPE_FAN_OUT_LIMIT + 1)
+            .cap
+            .get_cap_type(),
+        CapTag::CapNullCap
+    );
+}
+
+#[test]
+fn not_enough_memory() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let (root, slot) = setup(&mut arena, 4, 12, false);
+    let tcb = ObjectType::TCBObject;
+    let err = decode(slot, &root, tcb, 0, 1, 5).unwrap_err();
+    assert_eq!((err._type, err.memoryLeft), (seL4_NotEnoughMemory, 4096));
+
+    retype(&mut kernel, slot, &root, tcb, 1, 3);
+    assert_eq!(free_index(slot), (3 * 1024) >> 4);
+    let err = decode(slot, &root, tcb, 0, 4, 2).unwrap_err();
+    assert_eq!((err._type, err.memoryLeft), (seL4_NotEnoughMemory, 1024));
+    // 剩下的内存仍然可以按对齐分配更小的对象
+    retype(&mut kernel, slot, &root, ObjectType::EndpointObject, 4, 3);
+    let err = decode(slot, &root, tcb, 0, 7, 1).unwrap_err();
+    assert_eq!(
+        (err._type, err.memoryLeft),
+        (seL4_NotEnoughMemory, 1024 - 48)
+    );
+    let err = decode(slot, &root, ObjectType::UnytpedObject, 12, 7, 1).unwrap_err();
+    assert_eq!(err._type, seL4_NotEnoughMemory);
+}
+
+#[test]
+fn device_untyped() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let (root, slot) = setup(&mut arena, 4, 14, true);
+    let base = unsafe { (*slot).cap.get_untyped_ptr() };
+    unsafe { core::ptr::write_bytes(base as *mut u8, 0x5a, 1 << 14) };
+    // 设备内存中只能创建`frame`和`untyped`
+    for object_type in [
+        ObjectType::EndpointObject,
+        ObjectType::NotificationObject,
+        ObjectType::TCBObject,
+        ObjectType::CapTableObject,
+        ObjectType::PageTableObject,
+    ] {
+        let user_size = (object_type == ObjectType::CapTableObject) as usize;
+        let err = decode(slot, &root, object_type, user_size, 1, 1).unwrap_err();
+        assert_eq!(
+            (err._type, err.invalidArgumentNumber),
+            (seL4_InvalidArgument, 1)
+        );
+    }
+    retype(&mut kernel, slot, &root, ObjectType::NormalPageObject, 1, 2);
+    let r = decode(slot, &root, ObjectType::UnytpedObject, 12, 3, 1).unwrap();
+    assert!(r.device_memory);
+    assert!(invoke_untyped_retype(&r, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    let frame = cnode_slot(&root, 1).cap;
+    assert_eq!(frame.get_frame_is_device(), 1);
+    assert_eq!(cnode_slot(&root, 3).cap.get_untyped_is_device(), 1);
+    // 设备内存不会被清零
+    let memory = unsafe { core::slice::from_raw_parts(base as *const u8, 1 << 14) };
+    assert!(memory.iter().all(|b| *b == 0x5a));
+
+    // 没有子节点时重置只将`free index`归零
+    assert!(
+        unsafe { &mut *slot }.revoke_with(&mut kernel, &mut Unbounded)
+            == exception_t::EXCEPTION_NONE
+    );
+    let r = decode(slot, &root, ObjectType::NormalPageObject, 0, 1, 1).unwrap();
+    assert!(r.reset);
+    assert_eq!(r.region_base, base);
+    assert!(invoke_untyped_retype(&r, &mut kernel, &mut Unbounded) == exception_t::EXCEPTION_NONE);
+    assert!(memory.iter().all(|b| *b == 0x5a));
+}
+
+#[test]
+fn reset() {
+    let mut arena = Arena::new(20);
+    let mut kernel = HostedKernel::default();
+    let (root, slot) = setup(&mut arena, 4, 14, false);
+    let base = unsafe { (*slot).cap.get_untyped_ptr() };
+    retype(&mut kernel, slot, &root, ObjectType::NormalPageObject, 1, 3);
+    let r = decode(slot, &root, ObjectType::EndpointObject, 0, 4, 1).unwrap();
+    assert!(!r.reset);
+    assert_eq!(r.region_base, base + 3 * 4096);
+    unsafe { core::ptr::write_bytes(base as *mut u8, 0xa5, 3 * 4096) };
+
+    // 撤销后再次`retype`会先重置，被抢占时`free index`停在已清零的位置
+    assert!(
+        unsafe { &mut *slot }.revoke_with(&mut kernel, &mut Unbounded)
+            == exception_t::EXCEPTION_NONE
+    );
+    let r = decode(slot, &root, ObjectType::EndpointObject, 0, 4, 1).unwrap();
+    assert!(r.reset);
+    assert_eq!(r.region_base, base);
+    let mut budget = FixedBudget::new(4);
+    assert!(invoke_untyped_retype(&r, &mut kernel, &mut budget) == exception_t::EXCEPTION_PREEMTED);
+    assert_eq!(free_index(slot), (3 * 4096 - 4 * 256) >> 4);
+    assert_eq!(cnode_slot(&root, 4).cap.get_cap_type(), CapTag::CapNullCap);
+
+    // 重新解码和调用直到完成
+    let mut restarts = 0;
+    loop {
+        let r = decode(slot, &root, ObjectType::EndpointObject, 0, 4, 1).unwrap();
+        budget.refill(4);
+        if invoke_untyped_retype(&r, &mut kernel, &mut budget) == exception_t::EXCEPTION_NONE {
+            break;
+        }
+        restarts += 1;
+    }
+    assert_eq!(restarts, 3 * 4096 / 256 / 4 - 1);
+    assert_eq!(free_index(slot), 1);
+    assert_eq!(
+        cnode_slot(&root, 4).cap.get_cap_type(),
+        CapTag::CapEndpointCap
+    );
+    let memory = unsafe { core::slice::from_raw_parts(base as *const u8, 3 * 4096) };
+    assert!(memory.iter().all(|b| *b == 0));
+}
+
+#[test]
+fn reset_is_restartable() {
+    let report = check_restartability(
+        20,
+        |arena| {
+            let (root, slot) = setup(arena, 2, 13, false);
+            unsafe { (*slot).cap.set_untyped_free_index(300) };
+            (root, slot)
+        },
+        |slot, kernel, budget| slot.reset_untyped_with(kernel, budget),
+    )
+    .unwrap();
+    assert_eq!(report.work_units, (300 << 4) / 256 + 1);
+}
@@ -0,0 +1,174 @@
+//! `CSpaceWalker` and the reverse lookup built on it.
+#![cfg(feature = "hosted")]
+
+use sel4_common::structures::exception_t;
+use sel4_cspace::hosted::{cnode_slot, Arena};
+use sel4_cspace::interface::{
+    cap_t, cte_t, lookup_slot_for_cnode_op, slot_cptr, slot_cptrs, CSpaceWalker,
+};
+
+/// A root CNode of 4 slots with the 3 bit guard 0b101, slot 1 holds a CNode of 8 slots with the
+/// 2 bit guard 0b10, slot 2 an endpoint. Slot 5 of the inner CNode holds an endpoint.
+fn cspace(arena: &mut Arena) -> cap_t {
+    let root = arena.new_cnode(2, 3, 0b101);
+    let inner = arena.new_cnode(3, 2, 0b10);
+    cnode_slot(&root, 1).cap = inner;
+    cnode_slot(&root, 2).cap = arena.new_endpoint();
+    cnode_slot(&inner, 5).cap = arena.new_endpoint();
+    root
+}
+
+/// The CPtr of slot `index` of the root CNode, `guard | index`.
+fn root_cptr(index: usize) -> usize {
+    (0b101 << 2) | index
+}
+
+/// The CPtr of slot `index` of the inner CNode, `root_cptr(1) | guard | index`.
+fn inner_cptr(index: usize) -> usize {
+    (root_cptr(1) << 5) | (0b10 << 3) | index
+}
+
+fn lookup(root: &cap_t, cptr: usize, depth: usize) -> *mut cte_t {
+    let ret = lookup_slot_for_cnode_op(false, root, cptr, depth);
+    assert_eq!(ret.status, exception_t::EXCEPTION_NONE, "{cptr:#x} {depth}");
+    ret.slot
+}
+
+#[test]
+fn walk_two_levels() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 1).cap;
+    let found: Vec<_> = CSpaceWalker::new(&root)
+        .map(|(cptr, depth, slot)| (cptr, depth, slot.get_ptr()))
+        .collect();
+    let expected = [
+        (root_cptr(1), 5, cnode_slot(&root, 1).get_ptr()),
+        (inner_cptr(5), 10, cnode_slot(&inner, 5).get_ptr()),
+        (root_cptr(2), 5, cnode_slot(&root, 2).get_ptr()),
+    ];
+    assert_eq!(found, expected);
+    for (cptr, depth, slot) in expected {
+        assert_eq!(lookup(&root, cptr, depth) as usize, slot);
+    }
+
+    let ep = cnode_slot(&root, 2).cap;
+    assert_eq!(CSpaceWalker::new(&ep).count(), 0);
+}
+
+#[test]
+fn walk_cycles() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 1).cap;
+    cnode_slot(&inner, 0).cap = root;
+    cnode_slot(&root, 3).cap = root;
+
+    let mut walker = CSpaceWalker::new(&root);
+    let found: Vec<_> = walker
+        .by_ref()
+        .map(|(cptr, depth, _)| (cptr, depth))
+        .collect();
+    // 指回路径上`CNode`的`cap`本身会被列出，但不进入
+    assert_eq!(
+        found,
+        [
+            (root_cptr(1), 5),
+            (inner_cptr(0), 10),
+            (inner_cptr(5), 10),
+            (root_cptr(2), 5),
+            (root_cptr(3), 5),
+        ]
+    );
+    assert_eq!(walker.cycles(), 2);
+
+    // 同一个`CNode`经两条路径到达不算环
+    cnode_slot(&inner, 0).cap = cap_t::new_null_cap();
+    cnode_slot(&root, 3).cap = inner;
+    let mut walker = CSpaceWalker::new(&root);
+    assert_eq!(walker.by_ref().count(), 5);
+    assert_eq!(walker.cycles(), 0);
+}
+
+/// A chain of CNodes of 2 slots without guard, slot 1 of each holds the next one and slot 0 an
+/// endpoint. The CNodes are returned from the root.
+fn chain(arena: &mut Arena, len: usize) -> Vec<cap_t> {
+    let cnodes: Vec<_> = (0..len).map(|_| arena.new_cnode(1, 0, 0)).collect();
+    for pair in cnodes.windows(2) {
+        cnode_slot(&pair[0], 1).cap = pair[1];
+    }
+    for cnode in &cnodes {
+        cnode_slot(cnode, 0).cap = arena.new_endpoint();
+    }
+    cnodes
+}
+
+#[test]
+fn walk_depth_limit() {
+    let mut arena = Arena::new(16);
+    let cnodes = chain(&mut arena, 66);
+    let found: Vec<_> = CSpaceWalker::new(&cnodes[0]).collect();
+    // 每层消耗1位，`CPtr`用完64位后不再进入下一层
+    assert_eq!(found.len(), 2 * 64);
+    let (cptr, depth, slot) = found[2 * 64 - 1];
+    assert_eq!((cptr, depth), (usize::MAX, 64));
+    assert_eq!(slot.cap.get_cnode_ptr(), cnodes[64].get_cnode_ptr());
+    let (cptr, depth, slot) = found[2 * 63];
+    assert_eq!((cptr, depth), (usize::MAX - 1, 64));
+    assert!(core::ptr::eq(slot, cnode_slot(&cnodes[63], 0)));
+    assert_eq!(
+        lookup(&cnodes[0], cptr, depth),
+        cnode_slot(&cnodes[63], 0) as *mut _
+    );
+}
+
+#[test]
+fn reverse_lookup() {
+    let mut arena = Arena::new(16);
+    let root = cspace(&mut arena);
+    let inner = cnode_slot(&root, 1).cap;
+    let slot = |cnode: &cap_t, index: usize| cnode_slot(cnode, index) as *const cte_t;
+
+    assert_eq!(slot_cptr(&root, slot(&root, 2)), Some((root_cptr(2), 5)));
+    // 空`slot`也可以寻址
+    assert_eq!(slot_cptr(&root, slot(&root, 0)), Some((root_cptr(0), 5)));
+    assert_eq!(slot_cptr(&root, slot(&inner, 7)), Some((inner_cptr(7), 10)));
+    for index in [0, 7] {
+        let (cptr, depth) = slot_cptr(&root, slot(&inner, index)).unwrap();
+        assert_eq!(lookup(&root, cptr, depth), slot(&inner, index) as *mut _);
+    }
+    let other = arena.new_cnode(1, 0, 0);
+    assert_eq!(slot_cptr(&root, slot(&other, 0)), None);
+    // 不在`slot`边界上的地址
+    assert_eq!(
+        slot_cptr(&root, (slot(&root, 2) as usize + 8) as *const _),
+        None
+    );
+
+    // 经两个`cap`到达的`CNode`，`slot_cptr`取最短的一个
+    let mut short = inner;
+    short.set_cnode_guard_size(0);
+    short.set_cnode_guard(0);
+    cnode_slot(&root, 3).cap = short;
+    let found: Vec<_> = slot_cptrs(&root, slot(&inner, 5)).collect();
+    assert_eq!(found, [(inner_cptr(5), 10), ((root_cptr(3) << 3) | 5, 8)]);
+    assert_eq!(
+        slot_cptr(&root, slot(&inner, 5)),
+        Some(((root_cptr(3) << 3) | 5, 8))
+    );
+
+    // 有环时也会结束
+    cnode_slot(&inner, 0).cap = root;
+    assert_eq!(slot_cptrs(&root, slot(&root, 2)).count(), 1);
+    assert_eq!(slot_cptrs(&root, slot(&inner, 5)).count(), 2);
+}
+
+#[test]
+fn reverse_lookup_depth_limit() {
+    let mut arena = Arena::new(16);
+    let cnodes = chain(&mut arena, 66);
+    let deepest = cnode_slot(&cnodes[63], 1) as *const cte_t;
+    assert_eq!(slot_cptr(&cnodes[0], deepest), Some((usize::MAX, 64)));
+    // 第65层的`CNode`无法寻址
+    assert_eq!(slot_cptr(&cnodes[0], cnode_slot(&cnodes[64], 0)), None);
+}
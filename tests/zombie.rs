@@ -0,0 +1,154 @@
+//! Zombie caps built by the C-style `Zombie_new` and `ZombieCap::new`, and corrupted zombies.
+#![cfg(feature = "hosted")]
+
+use sel4_common::structures::exception_t;
+use sel4_cspace::compatibility::{ZombieType_ZombieTCB, Zombie_new};
+use sel4_cspace::hosted::{cnode_slot, Arena, HostedKernel};
+use sel4_cspace::interface::{cap_t, CapTag, Unbounded, ZombieCap, ZombieError, ZombieKind};
+
+#[test]
+fn zombie_new_checks() {
+    // `CNode`的`radix`为4时低5位存放数量
+    let cap = Zombie_new(16, 4, 0x10000).unwrap();
+    assert_eq!(
+        (cap.get_zombie_ptr(), cap.get_zombie_number()),
+        (Some(0x10000), Some(16))
+    );
+    assert_eq!((cap.get_zombie_id(), cap.get_zombie_type()), (0x10010, 4));
+    let cap = Zombie_new(5, ZombieType_ZombieTCB, 0x10400).unwrap();
+    assert_eq!(cap.get_zombie_id(), 0x10405);
+    assert_eq!(cap.get_zombie_kind(), Some(ZombieKind::Tcb));
+
+    assert_eq!(
+        Zombie_new(17, 4, 0x10000),
+        Err(ZombieError::NumberOutOfRange {
+            number: 17,
+            slots: 16
+        })
+    );
+    assert_eq!(
+        Zombie_new(1, 4, 0x10013),
+        Err(ZombieError::MisalignedPtr(0x10013))
+    );
+    assert_eq!(
+        Zombie_new(6, ZombieType_ZombieTCB, 0x10400),
+        Err(ZombieError::NumberOutOfRange {
+            number: 6,
+            slots: 5
+        })
+    );
+    // `radix`过大时`CNode`放不进内存
+    for _type in [60, 63, 65, 127] {
+        assert_eq!(
+            Zombie_new(7, _type, 0x10000),
+            Err(ZombieError::InvalidType(_type))
+        );
+    }
+    assert!(Zombie_new(7, 59, 0).is_ok());
+}
+
+#[test]
+fn zombie_cap_new_checks() {
+    let kind = ZombieKind::CNode { radix: 4 };
+    let zombie = ZombieCap::new(kind, 0x10000, 16).unwrap();
+    assert_eq!(
+        (zombie.kind(), zombie.ptr(), zombie.remaining_slots()),
+        (kind, 0x10000, 16)
+    );
+    assert_eq!(
+        Zombie_new(16, 4, 0x10000).unwrap().words,
+        zombie.as_cap().words
+    );
+    assert_eq!(
+        ZombieCap::new(kind, 0x10000, 17),
+        Err(ZombieError::NumberOutOfRange {
+            number: 17,
+            slots: 16
+        })
+    );
+    assert_eq!(
+        ZombieCap::new(kind, 0x10010, 1),
+        Err(ZombieError::MisalignedPtr(0x10010))
+    );
+    assert_eq!(
+        ZombieCap::new(ZombieKind::Tcb, 0x10400, 6),
+        Err(ZombieError::NumberOutOfRange {
+            number: 6,
+            slots: 5
+        })
+    );
+    let tcb = ZombieCap::new(ZombieKind::Tcb, 0x10400, 5).unwrap();
+    assert_eq!(tcb.as_cap().get_zombie_type(), ZombieType_ZombieTCB);
+}
+
+#[test]
+fn invalid_zombie_accessors() {
+    let cap = cap_t::new_zombie_cap(0x10000 | 7, 65);
+    assert_eq!(
+        (
+            cap.get_zombie_kind(),
+            cap.get_zombie_ptr(),
+            cap.get_zombie_number(),
+            cap.get_zombie_bit()
+        ),
+        (None, None, None, None)
+    );
+    let mut changed = cap;
+    assert_eq!(
+        changed.set_zombie_number(1),
+        Err(ZombieError::InvalidType(65))
+    );
+    assert_eq!(changed.words, cap.words);
+    assert!(ZombieCap::try_from(cap).is_err());
+
+    // `radix`为4时数量占5位，可以存下超过16的数
+    let mut cap = cap_t::new_zombie_cap(0x10000 | 31, 4);
+    assert_eq!(cap.get_zombie_ptr(), Some(0x10000));
+    assert_eq!(cap.get_zombie_number(), None);
+    assert!(ZombieCap::try_from(cap).is_err());
+    assert_eq!(
+        cap.set_zombie_number(17),
+        Err(ZombieError::NumberOutOfRange {
+            number: 17,
+            slots: 16
+        })
+    );
+    assert_eq!(cap.set_zombie_number(3), Ok(()));
+    assert_eq!(cap.get_zombie_number(), Some(3));
+    assert_eq!(ZombieCap::try_from(cap).unwrap().remaining_slots(), 3);
+}
+
+/// A corrupted zombie is deleted like a null cap, the slots of its object are left as they are.
+#[test]
+fn invalid_zombie_is_removable() {
+    let mut arena = Arena::new(16);
+    let mut kernel = HostedKernel::default();
+    let root = arena.new_cnode(2, 0, 0);
+    let cnode = arena.new_cnode(2, 0, 0);
+    for i in 0..4 {
+        cnode_slot(&cnode, i).cap = arena.new_endpoint();
+    }
+    let ptr = cnode.get_cnode_ptr();
+    // 类型无效，以及数量超过`slot`数
+    for (i, zombie) in [
+        cap_t::new_zombie_cap(ptr | 4, 65),
+        cap_t::new_zombie_cap(ptr | 7, 2),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let slot = cnode_slot(&root, i);
+        for exposed in [false, true] {
+            slot.cap = zombie;
+            let status = slot.delete_all_with(exposed, &mut kernel, &mut Unbounded);
+            assert_eq!(status, exception_t::EXCEPTION_NONE);
+            assert_eq!(slot.cap.get_cap_type(), CapTag::CapNullCap);
+        }
+    }
+    for i in 0..4 {
+        assert_eq!(
+            cnode_slot(&cnode, i).cap.get_cap_type(),
+            CapTag::CapEndpointCap
+        );
+    }
+}